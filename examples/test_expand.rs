@@ -1,10 +1,10 @@
 //! # Tests the EpubFile with the fatfs Library
 //!
 //! This example should be given a file or block device as the first and only
-//! argument. It will attempt to mount all four possible primary MBR
-//! partitions, one at a time, prints the root directory and will print a file
-//! called "README.TXT". It will then list the contents of the "TEST"
-//! sub-directory.
+//! argument. It will mount the first FAT volume, using the MBR or GPT
+//! partition table, prints the root directory and will print a file
+//! called "README.TXT". It will then expand an epub file and read its
+//! container.
 //!
 //! ```bash
 //! $ cargo run --example test_expand --features example -- /dev/mmcblk0
@@ -21,12 +21,33 @@ extern crate alloc;
 const FILE_TO_PRINT: &'static str = "README.TXT";
 
 use alloc::string::String;
+use epub::mbr::{Block, BlockCount, BlockIdx, VolumeIdx};
+use epub::volume::{self, BlockDevice};
 use epub::EPubFile;
-use fscommon::{BufStream, StreamSlice};
-use mbr;
-//use std::env;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use fatfs::Read;
+use std::io::{Seek, SeekFrom};
+
+/// a disk image or block device, read a block at a time
+struct FileBlockDevice(std::fs::File);
+
+impl BlockDevice for FileBlockDevice {
+    type Error = std::io::Error;
+
+    fn read_block(&mut self, idx: BlockIdx, block: &mut Block) -> Result<(), Self::Error> {
+        self.0.seek(SeekFrom::Start(idx.into_bytes()))?;
+        std::io::Read::read_exact(&mut self.0, &mut block.contents)
+    }
+
+    fn write_block(&mut self, idx: BlockIdx, block: &Block) -> Result<(), Self::Error> {
+        self.0.seek(SeekFrom::Start(idx.into_bytes()))?;
+        std::io::Write::write_all(&mut self.0, &block.contents)
+    }
+
+    fn num_blocks(&mut self) -> Result<BlockCount, Self::Error> {
+        let len = self.0.seek(SeekFrom::End(0))?;
+        Ok(BlockCount((len / Block::LEN as u64) as u32))
+    }
+}
 
 use env_logger::{Builder, Target};
 
@@ -37,35 +58,19 @@ fn main() {
     builder.init();
 
     let mut args = std::env::args().skip(1);
-    let filename = args.next().unwrap_or("/dev/sdd1".into());
+    let filename = args.next().unwrap_or("/dev/sdd".into());
     println!("Using filename: {:?}", filename);
 
     //////////////////////////////////////////////////////////////
 
-    let partitions = mbr::partition::read_partitions(PathBuf::from(filename.clone())).unwrap();
-    // get the first partition of type '6'
-    let mut fatpart: mbr::partition::Partition = partitions[0].clone();
-    for (i, p) in partitions.iter().enumerate() {
-        if p.p_type == 0x6 {
-            fatpart = p.clone();
-        }
-        println!("Partition {}: {:?}", i, p);
-    }
-
     let img_file = std::fs::OpenOptions::new()
         .read(true)
         .write(true)
         .open(filename)
         .unwrap();
 
-    // make the buf stream
-    let first_lba: u64 = (fatpart.p_lba * 512).into();
-    let last_lba: u64 = (fatpart.p_size * 512).into();
-    let stream_partition = StreamSlice::new(img_file, first_lba, last_lba + 1).unwrap();
-    let buf_stream = BufStream::new(stream_partition);
-
     // make the filesystem
-    let mut fs = fatfs::FileSystem::new(buf_stream, fatfs::FsOptions::new()).unwrap();
+    let mut fs = volume::mount_volume(FileBlockDevice(img_file), VolumeIdx(0)).unwrap();
     println!("fs created");
 
     //////////////////////////////////////////////////////////////
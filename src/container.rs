@@ -1,9 +1,9 @@
 //! the EPub Container
 //! https://www.w3.org/publishing/epub32/epub-ocf.html#sec-container-zip
 
-use crate::io::BufReader;
-use crate::EPubError;
-use alloc::{boxed::Box, string::String, vec::Vec};
+use crate::io::{self, BufReader, DefaultWorkspace, Workspace};
+use crate::{EPubError, ParseError};
+use alloc::{string::String, vec::Vec};
 use fatfs::{File, FileSystem, OemCpConverter, ReadWriteSeek, TimeProvider, Write};
 use log::{info, trace};
 use miniz_oxide::inflate::{core, TINFLStatus};
@@ -154,14 +154,18 @@ impl LocalFileHeader {
 
     /// inflate compressed data from a BufReader into a file
     ///
-    /// uses a lot of memory, 2x32k buffers
+    /// `window` is the inflate window, it must be a power of two of at least 32k.
+    /// `input` holds the compressed data read from the file, any size will do
     pub fn inflate<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
         &self,
         rdr: &mut BufReader<IO, TP, OCC>,
         output_file: &mut File<IO, TP, OCC>,
+        window: &mut [u8],
+        input: &mut [u8],
     ) -> Result<usize, EPubError<IO>> {
-        let mut input: Box<[u8; 32768]> = Box::new([0; 32768]);
-        let mut output: Box<[u8; 32768]> = Box::new([0; 32768]);
+        if window.len() < 32768 || !window.len().is_power_of_two() || input.is_empty() {
+            return Err(EPubError::InvalidWorkspace);
+        }
         let mut decomp = core::DecompressorOxide::new();
         decomp.init();
         info!(
@@ -169,10 +173,12 @@ impl LocalFileHeader {
             self.compressed_size, self.uncompressed_size
         );
         let mut count = 0;
+        // the window wraps, the decompressor refers back into it
+        let mut out_pos = 0;
         let mut bytes_to_go = self.compressed_size as usize;
         while bytes_to_go > 0 {
-            let (n, flags) = if bytes_to_go > 32768 {
-                (32768, core::inflate_flags::TINFL_FLAG_HAS_MORE_INPUT)
+            let (n, flags) = if bytes_to_go > input.len() {
+                (input.len(), core::inflate_flags::TINFL_FLAG_HAS_MORE_INPUT)
             } else {
                 (bytes_to_go, 0)
             };
@@ -183,7 +189,7 @@ impl LocalFileHeader {
             while keep_looping {
                 // following should loop until all input consumed
                 let (status, in_consumed, out_consumed) =
-                    core::decompress(&mut decomp, &input[in_start..n], &mut *output, 0, flags);
+                    core::decompress(&mut decomp, &input[in_start..n], window, out_pos, flags);
                 trace!(
                     "inflate [status {:?} incoming {} bytes outgoing {} bytes]",
                     status,
@@ -200,14 +206,15 @@ impl LocalFileHeader {
                     e => return Err(EPubError::Decompress(e)),
                 }
 
-                let mut out_start = 0;
-                while out_start < out_consumed {
-                    let n = output_file.write(&output[out_start..out_consumed])?;
+                let mut out_start = out_pos;
+                while out_start < out_pos + out_consumed {
+                    let n = output_file.write(&window[out_start..out_pos + out_consumed])?;
                     trace!("wrote {} bytes to file", n,);
                     out_start += n;
                 }
                 output_file.flush()?;
                 count += out_consumed;
+                out_pos = (out_pos + out_consumed) & (window.len() - 1);
             }
             bytes_to_go -= n;
         }
@@ -244,16 +251,28 @@ impl Container {
     pub fn get_container_rootfile<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
         &self,
         fs: &mut FileSystem<IO, TP, OCC>,
+    ) -> Result<Option<Rootfile>, EPubError<IO>> {
+        self.get_container_rootfile_with(fs, &mut io::heap_blocks())
+    }
+
+    /// get the root file entry from container.xml, using the caller's
+    /// `BufReader` blocks
+    pub fn get_container_rootfile_with<
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+        const N: usize,
+    >(
+        &self,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        blocks: &mut [[u8; N]; 2],
     ) -> Result<Option<Rootfile>, EPubError<IO>> {
         let container_file_name: String = self.expanded_file_path(Container::EPUB_CONTAINER_FILE);
-        let root_dir = fs.root_dir();
-        let container_file = root_dir.open_file(&container_file_name)?;
-        let mut rdr = BufReader::new(container_file)?;
         let mut p = Parser::new();
         let mut stack: Vec<Event> = Vec::new();
         let mut in_rootfiles = false;
         let mut root_file: Option<Rootfile> = None;
-        let lines = rdr.read_lines()?;
+        let lines = io::read_file_lines(&container_file_name, fs, blocks)?;
         for ln in lines {
             p.feed_str(&ln);
             for event in &mut p {
@@ -276,10 +295,10 @@ impl Container {
                                             in_rootfiles = false;
                                         }
                                         if in_rootfiles {
-                                            root_file = Some(Rootfile::new(
-                                                &start_tag,
-                                                &self.expanded_dir_path,
-                                            ));
+                                            let rf =
+                                                Rootfile::new(&start_tag, &self.expanded_dir_path)
+                                                    .map_err(|e| e.in_file(&container_file_name))?;
+                                            root_file = Some(rf);
                                         }
                                     }
                                     _ => (),
@@ -296,17 +315,45 @@ impl Container {
     }
 
     /// expand the epub file into the directory
+    ///
+    /// the buffers needed are allocated on the heap for the duration of the call
     pub fn expand<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
         &mut self,
         epub_filepath: &str,
         fs: &mut FileSystem<IO, TP, OCC>,
     ) -> Result<(), EPubError<IO>> {
+        let mut ws = DefaultWorkspace::boxed();
+        self.expand_with(epub_filepath, fs, &mut ws)
+    }
+
+    /// expand the epub file into the directory, using the caller's buffers
+    pub fn expand_with<
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+        const WINDOW: usize,
+        const INPUT: usize,
+        const BLOCK: usize,
+    >(
+        &mut self,
+        epub_filepath: &str,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        ws: &mut Workspace<WINDOW, INPUT, BLOCK>,
+    ) -> Result<(), EPubError<IO>> {
+        if !ws.is_valid() {
+            return Err(EPubError::InvalidWorkspace);
+        }
+        let Workspace {
+            window,
+            input,
+            blocks,
+        } = ws;
         // open the epub file
         let root_dir = fs.root_dir();
         let epub_file = root_dir.open_file(epub_filepath)?;
 
         // now expand the file
-        let mut rdr = BufReader::new(epub_file)?;
+        let mut rdr = BufReader::with_blocks(epub_file, blocks)?;
         loop {
             #[cfg(feature = "std")]
             log::trace!("{:?}", rdr);
@@ -324,15 +371,13 @@ impl Container {
                         let mut this_file = root_dir.create_file(filename.as_str())?;
                         // write the file, either compressed or not
                         if lfh.compression_method == 8 {
-                            lfh.inflate(&mut rdr, &mut this_file)?;
+                            lfh.inflate(&mut rdr, &mut this_file, window, input)?;
                         } else {
                             let mut bytes_to_go = lfh.uncompressed_size as usize;
                             while bytes_to_go > 0 {
-                                let mut n = if bytes_to_go > 256 { 256 } else { bytes_to_go };
-                                let mut v = Vec::new();
-                                v.resize(n, 0);
-                                n = rdr.read_to_array(&mut v[..n])?;
-                                this_file.write(&v[..n])?;
+                                let n = ::core::cmp::min(bytes_to_go, input.len());
+                                rdr.read_to_array(&mut input[..n])?;
+                                this_file.write_all(&input[..n])?;
                                 bytes_to_go -= n;
                             }
                         }
@@ -367,8 +412,13 @@ impl Container {
 /// represents rootfile section from container.xml
 #[derive(Debug)]
 pub struct Rootfile {
+    /// path of the package document on disk
     pub full_path: String,
     pub media_type: String,
+    /// the directory the container was expanded into
+    pub root_dir: String,
+    /// path of the package document relative to the container root
+    pub path: String,
 }
 
 /*
@@ -381,19 +431,25 @@ pub struct Rootfile {
 */
 
 impl Rootfile {
-    pub fn new(tag: &StartTag, leading_dir: &str) -> Rootfile {
-        if let Some(fp) = tag.attributes.get(&(String::from("full-path"), None)) {
-            if let Some(mtype) = tag.attributes.get(&(String::from("media-type"), None)) {
-                Rootfile {
-                    full_path: String::from(leading_dir) + "/" + fp,
-                    media_type: String::from(mtype),
-                }
-            } else {
-                panic!();
-            }
-        } else {
-            panic!();
-        }
+    /// create the rootfile from its tag, for a container expanded into `leading_dir`
+    pub fn new(tag: &StartTag, leading_dir: &str) -> Result<Rootfile, ParseError> {
+        let fp = tag
+            .attributes
+            .get(&(String::from("full-path"), None))
+            .ok_or_else(|| ParseError::missing_attribute(&tag.name, "full-path"))?;
+        let mtype = tag
+            .attributes
+            .get(&(String::from("media-type"), None))
+            .ok_or_else(|| ParseError::missing_attribute(&tag.name, "media-type"))?;
+        let path = io::resolve_href("", fp)
+            .map_err(|e| ParseError::invalid_href(&tag.name, "full-path", e))?
+            .path;
+        Ok(Rootfile {
+            full_path: io::join_path(leading_dir, &path),
+            media_type: String::from(mtype),
+            root_dir: String::from(leading_dir),
+            path,
+        })
     }
 }
 
@@ -401,6 +457,7 @@ impl Rootfile {
 mod tests {
 
     use super::*;
+    use crate::ParseErrorKind;
 
     #[test]
     fn test_rootfile() {
@@ -412,8 +469,9 @@ mod tests {
         for event in p {
             match event.unwrap() {
                 xml::Event::ElementStart(tag) => {
-                    let rootfile = Rootfile::new(&tag, &"CUR_BOOK");
+                    let rootfile = Rootfile::new(&tag, &"CUR_BOOK").unwrap();
                     assert_eq!(rootfile.full_path, "CUR_BOOK/OEBPS/9781718500457.opf");
+                    assert_eq!(rootfile.path, "OEBPS/9781718500457.opf");
                     assert_eq!(rootfile.media_type, "application/oebps-package+xml");
                 }
                 _ => (),
@@ -422,19 +480,29 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn test_badrootfile() {
-        let mut p = xml::Parser::new();
-        // feed data to be parsed
-        p.feed_str("<rootfile full-paths=\"OEBPS/9781718500457.opf\" media-type=\"application/oebps-package+xml\" />");
-
-        // get events for the fed data
-        for event in p {
-            match event.unwrap() {
-                xml::Event::ElementStart(tag) => {
-                    let _ = Rootfile::new(&tag, &"CUR_BOOK");
+        for (doc, kind) in &[
+            (
+                "<rootfile full-paths=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\" />",
+                ParseErrorKind::MissingAttribute("full-path"),
+            ),
+            (
+                "<rootfile full-path=\"OEBPS/content.opf\" />",
+                ParseErrorKind::MissingAttribute("media-type"),
+            ),
+            (
+                "<rootfile full-path=\"../content.opf\" media-type=\"application/oebps-package+xml\" />",
+                ParseErrorKind::InvalidHref("full-path", io::HrefError::OutsideContainer),
+            ),
+        ] {
+            let mut p = xml::Parser::new();
+            p.feed_str(doc);
+            for event in p {
+                if let xml::Event::ElementStart(tag) = event.unwrap() {
+                    let err = Rootfile::new(&tag, &"CUR_BOOK").unwrap_err();
+                    assert_eq!(err.element, "rootfile");
+                    assert_eq!(&err.kind, kind);
                 }
-                _ => (),
             }
         }
     }
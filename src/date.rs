@@ -0,0 +1,292 @@
+//! dates in the W3CDTF profile of ISO 8601 used by `dc:date` and
+//! `dcterms:modified`
+//! https://www.w3.org/TR/NOTE-datetime
+
+use core::cmp::Ordering;
+
+/// a date with the precision it was given in
+///
+/// dates compare by the instant they start at in UTC, and a less precise
+/// date sorts before a more precise one starting at the same instant
+#[derive(Debug, Clone, Copy)]
+pub struct Date {
+    pub year: i32,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+    pub time: Option<Time>,
+}
+
+/// the time of day of a `Date`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+    /// minutes east of UTC, `None` if no zone is given and UTC is assumed
+    pub offset: Option<i16>,
+}
+
+impl Date {
+    /// parse a date such as `2020`, `2020-05`, `2020-05-17` or
+    /// `2020-05-17T10:30:00.5+02:00`
+    ///
+    /// a date-time without a zone is accepted as UTC, and surrounding
+    /// whitespace is ignored
+    pub fn parse(value: &str) -> Option<Date> {
+        let value = value.trim();
+        let (date, time) = match value.find('T') {
+            Some(i) => (&value[..i], Some(&value[i + 1..])),
+            None => (value, None),
+        };
+        let mut parts = date.split('-');
+        let year = digits(parts.next()?, 4)? as i32;
+        let month = match parts.next() {
+            Some(m) => {
+                let m = digits(m, 2)? as u8;
+                if !(1..=12).contains(&m) {
+                    return None;
+                }
+                Some(m)
+            }
+            None => None,
+        };
+        let day = match parts.next() {
+            Some(d) => {
+                let d = digits(d, 2)? as u8;
+                if d == 0 || d > days_in_month(year, month?) {
+                    return None;
+                }
+                Some(d)
+            }
+            None => None,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        let time = match time {
+            Some(t) => {
+                day?;
+                Some(Time::parse(t)?)
+            }
+            None => None,
+        };
+        Some(Date {
+            year,
+            month,
+            day,
+            time,
+        })
+    }
+
+    /// seconds from 1970-01-01T00:00:00Z to the start of the date
+    pub fn timestamp(&self) -> i64 {
+        let days = days_from_civil(self.year, self.month.unwrap_or(1), self.day.unwrap_or(1));
+        let seconds = match &self.time {
+            Some(t) => {
+                i64::from(t.hour) * 3600 + i64::from(t.minute) * 60 + i64::from(t.second)
+                    - i64::from(t.offset.unwrap_or(0)) * 60
+            }
+            None => 0,
+        };
+        days * 86400 + seconds
+    }
+
+    /// 0 for a year up to 3 for a date-time
+    fn precision(&self) -> u8 {
+        match (self.month, self.day, self.time) {
+            (None, _, _) => 0,
+            (Some(_), None, _) => 1,
+            (Some(_), Some(_), None) => 2,
+            (Some(_), Some(_), Some(_)) => 3,
+        }
+    }
+
+    fn sort_key(&self) -> (i64, u32, u8) {
+        let nanosecond = self.time.map(|t| t.nanosecond).unwrap_or(0);
+        (self.timestamp(), nanosecond, self.precision())
+    }
+}
+
+impl PartialEq for Date {
+    fn eq(&self, other: &Date) -> bool {
+        self.sort_key() == other.sort_key()
+    }
+}
+
+impl Eq for Date {}
+
+impl PartialOrd for Date {
+    fn partial_cmp(&self, other: &Date) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Date {
+    fn cmp(&self, other: &Date) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl Time {
+    /// parse `hh:mm`, `hh:mm:ss` or `hh:mm:ss.s` followed by an optional zone
+    fn parse(value: &str) -> Option<Time> {
+        let (clock, offset) = match value.find(['Z', '+', '-']) {
+            Some(i) => (&value[..i], Some(parse_offset(&value[i..])?)),
+            None => (value, None),
+        };
+        let (clock, fraction) = match clock.find('.') {
+            Some(i) => (&clock[..i], Some(&clock[i + 1..])),
+            None => (clock, None),
+        };
+        let mut parts = clock.split(':');
+        let hour = digits(parts.next()?, 2)? as u8;
+        let minute = digits(parts.next()?, 2)? as u8;
+        let second = match parts.next() {
+            Some(s) => digits(s, 2)? as u8,
+            None => 0,
+        };
+        if parts.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        let nanosecond = match fraction {
+            Some(f) => {
+                if f.is_empty() || !f.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                // keep up to nine digits, padded out to nanoseconds
+                f.bytes()
+                    .chain(core::iter::repeat(b'0'))
+                    .take(9)
+                    .fold(0, |n, b| n * 10 + u32::from(b - b'0'))
+            }
+            None => 0,
+        };
+        Some(Time {
+            hour,
+            minute,
+            second,
+            nanosecond,
+            offset,
+        })
+    }
+}
+
+/// parse a zone, `Z` or `+hh:mm` or `-hh:mm`, into minutes east of UTC
+fn parse_offset(value: &str) -> Option<i16> {
+    if value == "Z" {
+        return Some(0);
+    }
+    let sign = match value.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let mut parts = value[1..].split(':');
+    let hours = digits(parts.next()?, 2)? as i16;
+    let minutes = digits(parts.next()?, 2)? as i16;
+    if parts.next().is_some() || hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// parse exactly `len` ascii digits
+fn digits(value: &str, len: usize) -> Option<u32> {
+    if value.len() != len || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// days from 1970-01-01 to the date in the proleptic Gregorian calendar
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let y = i64::from(year) - if month <= 2 { 1 } else { 0 };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = i64::from(month);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let d = Date::parse("2020").unwrap();
+        assert_eq!((d.year, d.month, d.day), (2020, None, None));
+        let d = Date::parse(" 2020-05 ").unwrap();
+        assert_eq!((d.year, d.month, d.day), (2020, Some(5), None));
+        let d = Date::parse("2020-02-29").unwrap();
+        assert_eq!((d.month, d.day, d.time), (Some(2), Some(29), None));
+        let d = Date::parse("2020-05-17T10:30:15.25+02:00").unwrap();
+        let t = d.time.unwrap();
+        assert_eq!((t.hour, t.minute, t.second), (10, 30, 15));
+        assert_eq!(t.nanosecond, 250_000_000);
+        assert_eq!(t.offset, Some(120));
+        let t = Date::parse("2020-05-17T10:30Z").unwrap().time.unwrap();
+        assert_eq!((t.second, t.offset), (0, Some(0)));
+        let t = Date::parse("2020-05-17T10:30:00").unwrap().time.unwrap();
+        assert_eq!(t.offset, None);
+        for bad in &[
+            "",
+            "20",
+            "2020-5",
+            "2019-02-29",
+            "2020-13",
+            "2020-04-31",
+            "2020-05T10:00Z",
+            "2020-05-17T25:00Z",
+            "2020-05-17T10:00+2",
+            "2020-05-17T10:00:00.Z",
+            "2020-05-17-01",
+            "May 2020",
+        ] {
+            assert!(Date::parse(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(Date::parse("1970").unwrap().timestamp(), 0);
+        assert_eq!(
+            Date::parse("2000-03-01T00:00:00Z").unwrap().timestamp(),
+            951_868_800
+        );
+        assert_eq!(
+            Date::parse("1969-12-31T23:00-01:00").unwrap().timestamp(),
+            0
+        );
+        assert_eq!(
+            Date::parse("1600-01-01").unwrap().timestamp(),
+            -11_676_096_000
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        let date = |s| Date::parse(s).unwrap();
+        assert!(date("2019-12-31") < date("2020"));
+        assert!(date("2020") < date("2020-01"));
+        assert!(date("2020-01-01T00:00:00Z") < date("2020-01-01T00:00:00.1Z"));
+        assert!(date("2020-06-01T12:00+02:00") < date("2020-06-01T11:00Z"));
+        assert_eq!(date("2020-06-01T12:00+02:00"), date("2020-06-01T10:00Z"));
+        assert_ne!(date("2020-01-01"), date("2020-01-01T00:00Z"));
+    }
+}
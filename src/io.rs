@@ -3,16 +3,117 @@
 //! BufReader uses 2 buffers to read a file
 //! the cursor will advance and automatically swap in the blocks as the cursor advances
 //! the cursor cannot go backwards
+//!
+//! The buffers can either be allocated by the reader, or provided by the caller
+//! through a [`Workspace`], so that no heap allocation is needed for them.
 
 use crate::{io, EPubError};
-use alloc::{string::String, vec::Vec};
+use alloc::{alloc::Layout, boxed::Box, string::String, vec, vec::Vec};
 use byteorder::{ByteOrder, LittleEndian};
-use fatfs::{File, FileSystem, OemCpConverter, Read, ReadWriteSeek, TimeProvider};
+use core::convert::TryInto;
+use core::ops::{Deref, DerefMut};
+use fatfs::{File, FileSystem, IoError, OemCpConverter, Read, ReadWriteSeek, TimeProvider};
 use log::{info, trace};
 
+/// Buffers used to expand and read an epub file, provided by the caller
+///
+/// - `WINDOW` is the inflate window. It must be a power of two, and at least
+///   32k to hold the largest distance a deflate stream can refer back to
+/// - `INPUT` is the size of the compressed data read from the file at once
+/// - `BLOCK` is the size of each of the two `BufReader` blocks
+///
+/// The struct is large, so place it in a `static` or another long lived
+/// location rather than on a small stack, or allocate it with
+/// `Workspace::boxed`.
+pub struct Workspace<const WINDOW: usize, const INPUT: usize, const BLOCK: usize> {
+    /// the inflate window, holds the decompressed output
+    pub window: [u8; WINDOW],
+    /// compressed input buffer
+    pub input: [u8; INPUT],
+    /// the `BufReader` blocks
+    pub blocks: [[u8; BLOCK]; 2],
+}
+
+/// the workspace used when the library allocates the buffers itself
+pub type DefaultWorkspace = Workspace<32768, 32768, BUFBLOCKSIZE>;
+
+impl<const WINDOW: usize, const INPUT: usize, const BLOCK: usize> Workspace<WINDOW, INPUT, BLOCK> {
+    /// create a zeroed workspace, usable in a `static` initializer
+    pub const fn new() -> Self {
+        Workspace {
+            window: [0; WINDOW],
+            input: [0; INPUT],
+            blocks: [[0; BLOCK]; 2],
+        }
+    }
+
+    /// allocate a zeroed workspace on the heap
+    ///
+    /// unlike `Box::new(Workspace::new())`, the workspace is never built on
+    /// the stack first
+    pub fn boxed() -> Box<Self> {
+        let layout = Layout::new::<Self>();
+        if layout.size() == 0 {
+            return Box::new(Self::new());
+        }
+        // SAFETY: the layout is that of `Self` and isn't zero sized, and
+        // the workspace is only byte arrays so all zeroes is a valid value
+        unsafe {
+            let ptr = alloc::alloc::alloc_zeroed(layout) as *mut Self;
+            if ptr.is_null() {
+                alloc::alloc::handle_alloc_error(layout);
+            }
+            Box::from_raw(ptr)
+        }
+    }
+
+    /// check that the sizes can be used for expansion
+    ///
+    /// the blocks have to hold the 4 byte values read by `BufReader::peek4`
+    pub fn is_valid(&self) -> bool {
+        WINDOW >= 32768 && WINDOW.is_power_of_two() && INPUT > 0 && BLOCK >= 4
+    }
+}
+
+impl<const WINDOW: usize, const INPUT: usize, const BLOCK: usize> Default
+    for Workspace<WINDOW, INPUT, BLOCK>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// a block buffer, either allocated by the reader or borrowed from the caller
+enum BlockBuf<'a> {
+    Owned(Vec<u8>),
+    Borrowed(&'a mut [u8]),
+}
+
+impl<'a> Deref for BlockBuf<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            BlockBuf::Owned(v) => v,
+            BlockBuf::Borrowed(s) => s,
+        }
+    }
+}
+
+impl<'a> DerefMut for BlockBuf<'a> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            BlockBuf::Owned(v) => v,
+            BlockBuf::Borrowed(s) => s,
+        }
+    }
+}
+
 /// Read data from blocks serially
 ///
-/// Once the end of a block is reached, another will be retrieved
+/// Once the end of a block is reached, another will be retrieved. Requests
+/// of at least a block in size bypass the buffers and are read straight
+/// into the caller's array.
 //#[derive(Debug)]
 pub struct BufReader<'a, IO, TP, OCC>
 where
@@ -23,30 +124,42 @@ where
     /// the file we are reading from
     file: File<'a, IO, TP, OCC>,
     /// the block buffers
-    blocks: Vec<Vec<u8>>,
+    blocks: [BlockBuf<'a>; 2],
+    /// number of valid bytes in each block buffer
+    lens: [usize; 2],
     /// which block buffer is the cursor in
     block_idx: usize,
     /// the cursor position in the block_idx buffer
     cursor: usize,
     /// peek has rolled over the boundary, so don't load a new block
     peek_rolled: bool,
+    /// a peek is in progress, so every byte read has to go through the blocks
+    peeking: bool,
 }
 
 #[cfg(feature = "std")]
 impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> std::fmt::Debug
     for BufReader<'a, IO, TP, OCC>
 {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{:?} block_idx: {} cursor: {} loaded: {}",
-            self.blocks, self.block_idx, self.cursor, self.loaded
+            "lens: {:?} block_idx: {} cursor: {} peek_rolled: {}",
+            self.lens, self.block_idx, self.cursor, self.peek_rolled
         )
     }
 }
 
 const BUFBLOCKSIZE: usize = 512;
 
+/// allocate a pair of `BufReader` blocks on the heap
+pub(crate) fn heap_blocks() -> Box<[[u8; BUFBLOCKSIZE]; 2]> {
+    match vec![[0; BUFBLOCKSIZE]; 2].into_boxed_slice().try_into() {
+        Ok(blocks) => blocks,
+        Err(_) => unreachable!(),
+    }
+}
+
 impl<'a, IO, TP, OCC> BufReader<'a, IO, TP, OCC>
 where
     IO: ReadWriteSeek,
@@ -54,11 +167,31 @@ where
     OCC: OemCpConverter,
 {
     /// create a BufReader attached to the file
-    pub fn new(file: File<IO, TP, OCC>) -> Result<BufReader<IO, TP, OCC>, EPubError<IO>> {
+    ///
+    /// the block buffers are allocated on the heap
+    pub fn new(file: File<'a, IO, TP, OCC>) -> Result<BufReader<'a, IO, TP, OCC>, EPubError<IO>> {
         info!("Creating BufReader");
-        let mut blocks = Vec::new();
-        blocks.push(Vec::new());
-        blocks.push(Vec::new());
+        let blocks = [
+            BlockBuf::Owned(vec![0; BUFBLOCKSIZE]),
+            BlockBuf::Owned(vec![0; BUFBLOCKSIZE]),
+        ];
+        BufReader::with_block_bufs(file, blocks)
+    }
+
+    /// create a BufReader attached to the file, using the caller's block buffers
+    pub fn with_blocks<const N: usize>(
+        file: File<'a, IO, TP, OCC>,
+        blocks: &'a mut [[u8; N]; 2],
+    ) -> Result<BufReader<'a, IO, TP, OCC>, EPubError<IO>> {
+        info!("Creating BufReader with {} byte blocks", N);
+        let [b0, b1] = blocks;
+        BufReader::with_block_bufs(file, [BlockBuf::Borrowed(b0), BlockBuf::Borrowed(b1)])
+    }
+
+    fn with_block_bufs(
+        file: File<'a, IO, TP, OCC>,
+        blocks: [BlockBuf<'a>; 2],
+    ) -> Result<BufReader<'a, IO, TP, OCC>, EPubError<IO>> {
         // start out with this idx, so 0 position block is loaded below
         let block_idx = 1;
         let cursor = 0;
@@ -66,42 +199,45 @@ where
         let mut rdr = BufReader {
             file,
             blocks,
+            lens: [0, 0],
             block_idx,
             cursor,
             peek_rolled,
+            peeking: false,
         };
         rdr.load_block()?;
         rdr.block_idx = 0;
         Ok(rdr)
     }
 
-    /// load a block into a buffer slot
+    /// the size of each block buffer
+    fn block_size(&self) -> usize {
+        self.blocks[0].len()
+    }
+
+    /// load a block into the buffer slot that the cursor is not in
+    ///
+    /// returns the number of bytes in the loaded block, 0 at end of file
     fn load_block(&mut self) -> Result<usize, EPubError<IO>> {
+        let next = self.block_idx ^ 1;
         if self.peek_rolled {
             self.peek_rolled = false;
-            return Ok(0);
+            return Ok(self.lens[next]);
         }
-        trace!("Loading Block into position {}", self.block_idx ^ 1);
-        let buf = if self.block_idx == 0 {
-            self.blocks[1].resize(BUFBLOCKSIZE, 0);
-            &mut self.blocks[1][0..BUFBLOCKSIZE]
-        } else {
-            self.blocks[0].resize(BUFBLOCKSIZE, 0);
-            &mut self.blocks[0][0..BUFBLOCKSIZE]
-        };
-        // TODO: it may not read all bytes, so need to retry
-        let n = self.file.read(buf)?;
-        if n != BUFBLOCKSIZE {
+        trace!("Loading Block into position {}", next);
+        let n = read_full(&mut self.file, &mut self.blocks[next])?;
+        if n != self.block_size() {
             trace!("load_block: short load of {} bytes", n);
-            if self.block_idx == 0 {
-                self.blocks[1].resize(n, 0);
-            } else {
-                self.blocks[0].resize(n, 0);
-            }
         }
+        self.lens[next] = n;
         Ok(n)
     }
 
+    /// the unread bytes in the current block
+    fn current(&self) -> &[u8] {
+        &self.blocks[self.block_idx][self.cursor..self.lens[self.block_idx]]
+    }
+
     /// read 1 byte from file
     pub fn read1(&mut self) -> Result<u8, EPubError<IO>> {
         let mut arr = [0u8; 1];
@@ -127,7 +263,11 @@ where
     pub fn peek4(&mut self) -> Result<u32, EPubError<IO>> {
         let cur = self.cursor;
         let idx = self.block_idx;
-        let peekee = self.read4()?;
+        // a direct read from the file could not be given back
+        self.peeking = true;
+        let peekee = self.read4();
+        self.peeking = false;
+        let peekee = peekee?;
         // restore previous state
         self.cursor = cur;
         if idx != self.block_idx {
@@ -138,48 +278,42 @@ where
     }
 
     /// read from file into an array
+    ///
+    /// the array is always filled, reaching the end of the file first is an error
     pub fn read_to_array(&mut self, arr: &mut [u8]) -> Result<usize, EPubError<IO>> {
         trace!("read {} bytes to array", arr.len());
-        let mut arr_idx = 0;
         let nbytes = arr.len();
+        let mut arr_idx = 0;
         while arr_idx < nbytes {
-            let n = if arr_idx + self.blocks[self.block_idx].len() < nbytes {
-                self.blocks[self.block_idx].len()
-            } else {
-                nbytes - arr_idx
-            };
-            if self.cursor + n < self.blocks[self.block_idx].len() {
-                for i in 0..n {
-                    arr[arr_idx + i] = self.blocks[self.block_idx][self.cursor + i];
-                }
+            let wanted = nbytes - arr_idx;
+            let avail = self.current().len();
+            if avail > 0 {
+                let n = core::cmp::min(avail, wanted);
                 trace!(
                     "read_to_array {} bytes at {}:{}",
                     n,
                     self.block_idx,
                     self.cursor
                 );
+                arr[arr_idx..arr_idx + n].copy_from_slice(&self.current()[..n]);
                 self.cursor += n;
+                arr_idx += n;
+            } else if wanted >= self.block_size() && !self.peek_rolled && !self.peeking {
+                // large read, go straight to the file
+                trace!("read_to_array {} bytes direct from file", wanted);
+                let n = read_full(&mut self.file, &mut arr[arr_idx..])?;
+                if n != wanted {
+                    return Err(EPubError::IO(fatfs::Error::UnexpectedEof));
+                }
+                arr_idx += n;
             } else {
                 trace!("read block rollover");
-                self.load_block()?;
-                let j = self.blocks[self.block_idx].len() - self.cursor;
-                trace!(
-                    "read_to_array {} bytes at {}:{}",
-                    j,
-                    self.block_idx,
-                    self.cursor
-                );
-                for i in 0..j {
-                    arr[arr_idx + i] = self.blocks[self.block_idx][self.cursor + i];
+                if self.load_block()? == 0 {
+                    return Err(EPubError::IO(fatfs::Error::UnexpectedEof));
                 }
                 self.block_idx ^= 1;
-                trace!("read_to_array {} bytes at {}:{}", n - j, self.block_idx, 0);
-                for i in 0..n - j {
-                    arr[arr_idx + i + j] = self.blocks[self.block_idx][i];
-                }
-                self.cursor = n - j;
+                self.cursor = 0;
             }
-            arr_idx += n;
             trace!("read_to_array progress:{} bytes", arr_idx);
         }
         Ok(nbytes)
@@ -187,29 +321,23 @@ where
 
     /// read lines from file
     pub fn read_lines(&mut self) -> Result<alloc::vec::Vec<alloc::string::String>, EPubError<IO>> {
-        // TODO: make sure that file hasn't yet been read
         let mut lines = alloc::vec::Vec::new();
         let mut ln = alloc::vec::Vec::new();
         trace!("read_lines");
         loop {
-            let n = self.blocks[self.block_idx].len();
-            let mut start = 0;
-            for i in 0..n {
-                if self.blocks[self.block_idx][i] == b'\n' {
-                    ln.extend_from_slice(&self.blocks[self.block_idx][start..i + 1]);
-                    let mut newln = alloc::vec::Vec::new();
-                    newln.extend(ln.iter().copied());
+            for chunk in self.current().split_inclusive(|b| *b == b'\n') {
+                ln.extend_from_slice(chunk);
+                if chunk.ends_with(b"\n") {
                     lines.push(alloc::string::String::from_utf8(ln)?);
-                    trace!("read_lines line[{}:{}]", start, i + 1);
                     ln = alloc::vec::Vec::new();
-                    start = i + 1;
                 }
             }
-            ln.extend_from_slice(&self.blocks[self.block_idx][start..n]);
+            self.cursor = self.lens[self.block_idx];
             if self.load_block()? == 0 {
                 break;
             }
             self.block_idx ^= 1;
+            self.cursor = 0;
         }
         if !ln.is_empty() {
             lines.push(alloc::string::String::from_utf8(ln)?);
@@ -219,6 +347,213 @@ where
     }
 }
 
+/// read the lines of the file at `file_name`, relative to the root directory,
+/// using the caller's block buffers
+pub fn read_file_lines<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter, const N: usize>(
+    file_name: &str,
+    fs: &mut FileSystem<IO, TP, OCC>,
+    blocks: &mut [[u8; N]; 2],
+) -> Result<Vec<String>, EPubError<IO>> {
+    let root_dir = fs.root_dir();
+    let file = root_dir.open_file(file_name)?;
+    info!("Opened '{}'", file_name);
+    let mut rdr = BufReader::with_blocks(file, blocks)?;
+    rdr.read_lines()
+}
+
+/// read from `rdr` until `buf` is full or the end of the file is reached
+///
+/// a single read may return fewer bytes than asked for, so keep asking
+/// until it returns 0. Returns the number of bytes read.
+pub fn read_full<R: Read>(rdr: &mut R, buf: &mut [u8]) -> Result<usize, R::Error> {
+    let mut n = 0;
+    while n < buf.len() {
+        match rdr.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(cnt) => n += cnt,
+            Err(e) if e.is_interrupted() => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// errors found while resolving an href
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HrefError {
+    /// the path goes above the root of the container
+    OutsideContainer,
+    /// the href refers to a remote resource, not a file in the container
+    Remote,
+    /// bad percent encoding, or the decoded path is not UTF-8
+    InvalidEncoding,
+}
+
+/// an href resolved to a file in the container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Href {
+    /// normalized path relative to the container root, without a leading '/'
+    pub path: String,
+    /// the decoded `#fragment`, if any
+    pub fragment: Option<String>,
+    /// the raw `?query`, if any
+    pub query: Option<String>,
+}
+
+/// resolve an href against the container relative path of the document it was found in
+///
+/// `.` and `..` segments are normalized and the path and fragment are percent-decoded.
+/// An href with only a fragment refers to the base document itself.
+pub fn resolve_href(base: &str, href: &str) -> Result<Href, HrefError> {
+    let (rest, fragment) = match href.find('#') {
+        Some(i) => (&href[..i], Some(percent_decode(&href[i + 1..])?)),
+        None => (href, None),
+    };
+    let (rest, query) = match rest.find('?') {
+        Some(i) => (&rest[..i], Some(String::from(&rest[i + 1..]))),
+        None => (rest, None),
+    };
+    if has_scheme(rest) {
+        return Err(HrefError::Remote);
+    }
+    let mut segments: Vec<String> = Vec::new();
+    if rest.is_empty() {
+        segments = split_path(base);
+    } else if !rest.starts_with('/') {
+        segments = split_path(dirname(base));
+    }
+    for chunk in rest.split('/') {
+        let seg = percent_decode(chunk)?;
+        if seg.contains('/') {
+            return Err(HrefError::InvalidEncoding);
+        }
+        match seg.as_str() {
+            "" | "." => (),
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(HrefError::OutsideContainer);
+                }
+            }
+            _ => segments.push(seg),
+        }
+    }
+    Ok(Href {
+        path: segments.join("/"),
+        fragment,
+        query,
+    })
+}
+
+/// the href from the document at `base` to the file at `path`, both relative
+/// to the container root
+///
+/// the reverse of `resolve_href`, characters that would be read as part of
+/// the href syntax are percent-encoded
+pub fn relative_href(base: &str, path: &str) -> String {
+    let from = split_path(dirname(base));
+    let to = split_path(path);
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut segments: Vec<String> = Vec::new();
+    for _ in common..from.len() {
+        segments.push(String::from(".."));
+    }
+    for seg in &to[common..] {
+        segments.push(percent_encode(seg));
+    }
+    let href = segments.join("/");
+    // a leading segment with a ':' would read as a URL scheme
+    if common == from.len() && has_scheme(&href) {
+        String::from("./") + &href
+    } else {
+        href
+    }
+}
+
+/// encode the characters of a path segment that have a meaning in an href
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '%' | '#' | '?' | ' ' => {
+                out += &alloc::format!("%{:02X}", c as u32);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// does the href start with a URL scheme, ie `http:`
+fn has_scheme(href: &str) -> bool {
+    match href.find(':') {
+        Some(i) if i > 0 => {
+            let scheme = &href[..i];
+            scheme.as_bytes()[0].is_ascii_alphabetic()
+                && scheme
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'-' || b == b'.')
+        }
+        _ => false,
+    }
+}
+
+/// decode `%XX` escapes in a string
+pub fn percent_decode(s: &str) -> Result<String, HrefError> {
+    if !s.contains('%') {
+        return Ok(String::from(s));
+    }
+    let bytes = s.as_bytes();
+    let mut v = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if i + 2 >= bytes.len() {
+                return Err(HrefError::InvalidEncoding);
+            }
+            let hi = hex_value(bytes[i + 1]).ok_or(HrefError::InvalidEncoding)?;
+            let lo = hex_value(bytes[i + 2]).ok_or(HrefError::InvalidEncoding)?;
+            v.push(hi << 4 | lo);
+            i += 3;
+        } else {
+            v.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(v).map_err(|_| HrefError::InvalidEncoding)
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// the directory part of a path, everything before the last '/'
+pub fn dirname(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(i) => &path[..i],
+        None => "",
+    }
+}
+
+/// join a directory and a relative path with a '/'
+pub fn join_path(dir: &str, path: &str) -> String {
+    if dir.is_empty() {
+        String::from(path)
+    } else if path.is_empty() {
+        String::from(dir)
+    } else {
+        String::from(dir.trim_end_matches('/')) + "/" + path
+    }
+}
+
 /// function to take a path, return the basename and the extension
 /// of the filename in the path. All leading directories are stripped
 /// from the basename
@@ -279,6 +614,151 @@ pub fn create_dirs<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
 mod tests {
     use super::*;
 
+    /// reader that hands out at most 3 bytes per read
+    struct ShortReader {
+        data: Vec<u8>,
+        pos: usize,
+    }
+
+    impl fatfs::IoBase for ShortReader {
+        type Error = ();
+    }
+
+    impl Read for ShortReader {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+            let n = core::cmp::min(3, core::cmp::min(buf.len(), self.data.len() - self.pos));
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_full() {
+        let mut rdr = ShortReader {
+            data: (0..20).collect(),
+            pos: 0,
+        };
+        let mut buf = [0u8; 16];
+        assert_eq!(read_full(&mut rdr, &mut buf), Ok(16));
+        assert_eq!(buf[15], 15);
+        assert_eq!(read_full(&mut rdr, &mut buf), Ok(4));
+        assert_eq!(&buf[..4], &[16, 17, 18, 19]);
+        assert_eq!(read_full(&mut rdr, &mut buf), Ok(0));
+    }
+
+    #[test]
+    fn test_peek4_block_boundary() {
+        let mut disk = fatfs::StdIoWrapper::from(std::io::Cursor::new(vec![0u8; 1024 * 1024]));
+        fatfs::format_volume(&mut disk, fatfs::FormatVolumeOptions::new()).unwrap();
+        let fs = FileSystem::new(disk, fatfs::FsOptions::new()).unwrap();
+        let mut file = fs.root_dir().create_file("data.bin").unwrap();
+        fatfs::Write::write_all(&mut file, &(0..16).collect::<Vec<u8>>()).unwrap();
+        drop(file);
+        let file = fs.root_dir().open_file("data.bin").unwrap();
+        let mut blocks = [[0u8; 4]; 2];
+        let mut rdr = BufReader::with_blocks(file, &mut blocks).unwrap();
+        // the cursor ends up at the end of the first block
+        assert_eq!(rdr.read4().unwrap(), 0x0302_0100);
+        assert_eq!(rdr.peek4().unwrap(), 0x0706_0504);
+        assert_eq!(rdr.read4().unwrap(), 0x0706_0504);
+        assert_eq!(rdr.read1().unwrap(), 8);
+        assert_eq!(rdr.peek4().unwrap(), 0x0C0B_0A09);
+        assert_eq!(rdr.read4().unwrap(), 0x0C0B_0A09);
+        assert_eq!(rdr.read2().unwrap(), 0x0E0D);
+    }
+
+    #[test]
+    fn test_workspace_sizes() {
+        assert!(Workspace::<32768, 1024, 512>::new().is_valid());
+        assert!(Workspace::<65536, 32768, 4096>::new().is_valid());
+        assert!(!Workspace::<16384, 1024, 512>::new().is_valid());
+        assert!(!Workspace::<40000, 1024, 512>::new().is_valid());
+        assert!(!Workspace::<32768, 1024, 2>::new().is_valid());
+        let ws = Workspace::<32768, 1024, 512>::boxed();
+        assert!(ws.is_valid());
+        assert!(ws.window.iter().chain(ws.blocks[1].iter()).all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_resolve_href() {
+        let h = resolve_href("OEBPS/content.opf", "xhtml/ch01.xhtml").unwrap();
+        assert_eq!(h.path, "OEBPS/xhtml/ch01.xhtml");
+        assert_eq!(h.fragment, None);
+        assert_eq!(h.query, None);
+        let h = resolve_href("OEBPS/toc/toc.ncx", "../xhtml/./ch%2001.xhtml#sec%202").unwrap();
+        assert_eq!(h.path, "OEBPS/xhtml/ch 01.xhtml");
+        assert_eq!(h.fragment.unwrap(), "sec 2");
+        let h = resolve_href("content.opf", "text/ch.xhtml?v=1#top").unwrap();
+        assert_eq!(h.path, "text/ch.xhtml");
+        assert_eq!(h.query.unwrap(), "v=1");
+        assert_eq!(h.fragment.unwrap(), "top");
+        // the basename also appearing as a directory name
+        let h = resolve_href("book/book.opf/book.opf", "a.xhtml").unwrap();
+        assert_eq!(h.path, "book/book.opf/a.xhtml");
+    }
+
+    #[test]
+    fn test_resolve_href_special() {
+        let h = resolve_href("OEBPS/ch01.xhtml", "#note1").unwrap();
+        assert_eq!(h.path, "OEBPS/ch01.xhtml");
+        assert_eq!(h.fragment.unwrap(), "note1");
+        let h = resolve_href("OEBPS/ch01.xhtml", "/images/cover.jpg").unwrap();
+        assert_eq!(h.path, "images/cover.jpg");
+        assert_eq!(
+            resolve_href("OEBPS/content.opf", "../../etc/passwd"),
+            Err(HrefError::OutsideContainer)
+        );
+        assert_eq!(
+            resolve_href("OEBPS/content.opf", "http://example.com/a.css"),
+            Err(HrefError::Remote)
+        );
+        assert_eq!(
+            resolve_href("OEBPS/content.opf", "bad%2"),
+            Err(HrefError::InvalidEncoding)
+        );
+        assert_eq!(
+            resolve_href("OEBPS/content.opf", "a%2Fb.xhtml"),
+            Err(HrefError::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn test_relative_href() {
+        assert_eq!(
+            relative_href("OEBPS/content.opf", "OEBPS/xhtml/ch1.xhtml"),
+            "xhtml/ch1.xhtml"
+        );
+        assert_eq!(
+            relative_href("OEBPS/nav/nav.xhtml", "OEBPS/xhtml/ch1.xhtml"),
+            "../xhtml/ch1.xhtml"
+        );
+        assert_eq!(
+            relative_href("toc.ncx", "OEBPS/ch 1#.xhtml"),
+            "OEBPS/ch%201%23.xhtml"
+        );
+        assert_eq!(relative_href("toc.ncx", "a:b.xhtml"), "./a:b.xhtml");
+        for (base, path) in &[
+            ("OEBPS/nav/nav.xhtml", "text/ch 1%.xhtml"),
+            ("a/b/c.ncx", "a/b/c.xhtml"),
+        ] {
+            let h = resolve_href(base, &relative_href(base, path)).unwrap();
+            assert_eq!(h.path, *path);
+            assert_eq!(h.fragment, None);
+        }
+    }
+
+    #[test]
+    fn test_dirname_join() {
+        assert_eq!(dirname("OEBPS/content.opf"), "OEBPS");
+        assert_eq!(dirname("content.opf"), "");
+        assert_eq!(
+            join_path("CUR_BOOK/expanded", "OEBPS/a.xhtml"),
+            "CUR_BOOK/expanded/OEBPS/a.xhtml"
+        );
+        assert_eq!(join_path("", "a.xhtml"), "a.xhtml");
+    }
+
     #[test]
     fn test_split_path() {
         let s = String::from("/this/path/is/here.txt");
@@ -26,10 +26,12 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 pub mod container;
+pub mod date;
 pub mod io;
 pub mod mbr;
 pub mod navigation;
 pub mod package;
+pub mod volume;
 
 // for testing we want to have std available
 #[cfg(test)]
@@ -40,19 +42,101 @@ use std::prelude::*;
 
 extern crate alloc;
 
-use alloc::{string::FromUtf8Error, string::String};
+use alloc::{string::FromUtf8Error, string::String, vec::Vec};
 use container::Container;
 use core::str::Utf8Error;
 use fatfs::{FileSystem, OemCpConverter, ReadWriteSeek, TimeProvider, Write};
-use io::BufReader;
-use log::{info, trace};
+use io::{BufReader, DefaultWorkspace, Workspace};
+use log::{info, trace, warn};
 use miniz_oxide::inflate::TINFLStatus;
 use navigation::Toc;
-use package::Package;
+use package::{Cover, Landmark, Package, SpinePosition, TocSource};
 use xml;
 
+/// what is wrong with an element in one of the book's documents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// a required attribute is not present
+    MissingAttribute(&'static str),
+    /// an attribute holds a value that can't be parsed
+    InvalidAttribute(&'static str),
+    /// the element was closed by an end tag with this name
+    MismatchedEnd(String),
+    /// an attribute holds an href that can't be resolved
+    InvalidHref(&'static str, io::HrefError),
+    /// another element already has this id
+    DuplicateId(String),
+}
+
+/// a malformed element in one of the book's documents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// the document the element is in
+    pub file: String,
+    /// the element name
+    pub element: String,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    /// a required attribute is missing from `element`
+    pub fn missing_attribute(element: &str, attribute: &'static str) -> ParseError {
+        ParseError {
+            file: String::new(),
+            element: String::from(element),
+            kind: ParseErrorKind::MissingAttribute(attribute),
+        }
+    }
+
+    /// an attribute of `element` holds a value that can't be parsed
+    pub fn invalid_attribute(element: &str, attribute: &'static str) -> ParseError {
+        ParseError {
+            file: String::new(),
+            element: String::from(element),
+            kind: ParseErrorKind::InvalidAttribute(attribute),
+        }
+    }
+
+    /// an attribute of `element` holds an href that can't be resolved
+    pub fn invalid_href(
+        element: &str,
+        attribute: &'static str,
+        error: io::HrefError,
+    ) -> ParseError {
+        ParseError {
+            file: String::new(),
+            element: String::from(element),
+            kind: ParseErrorKind::InvalidHref(attribute, error),
+        }
+    }
+
+    /// `element` has an id that is already taken
+    pub fn duplicate_id(element: &str, id: &str) -> ParseError {
+        ParseError {
+            file: String::new(),
+            element: String::from(element),
+            kind: ParseErrorKind::DuplicateId(String::from(id)),
+        }
+    }
+
+    /// record the document the error was found in
+    pub fn in_file(mut self, file: &str) -> ParseError {
+        self.file = String::from(file);
+        self
+    }
+}
+
+/// how to handle malformed elements when reading the book's documents
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// stop at the first malformed element
+    #[default]
+    Strict,
+    /// skip malformed elements, recording a warning for each
+    Lenient,
+}
+
 /// an error
-#[derive(Debug)]
 pub enum EPubError<IO>
 where
     IO: ReadWriteSeek,
@@ -63,11 +147,14 @@ where
     EPubFileNotExpanded,
     FormatError(&'static str),
     NoSuchVolume,
+    InvalidWorkspace,
     Decompress(TINFLStatus),
     IO(fatfs::Error<IO::Error>),
     UTF8(Utf8Error),
     FromUTF8(FromUtf8Error),
     XmlParseErr(xml::ParserError),
+    Href(io::HrefError),
+    Parse(ParseError),
 }
 
 impl<IO> From<fatfs::Error<IO::Error>> for EPubError<IO>
@@ -79,6 +166,24 @@ where
     }
 }
 
+impl<IO> From<io::HrefError> for EPubError<IO>
+where
+    IO: ReadWriteSeek,
+{
+    fn from(error: io::HrefError) -> Self {
+        EPubError::Href(error)
+    }
+}
+
+impl<IO> From<ParseError> for EPubError<IO>
+where
+    IO: ReadWriteSeek,
+{
+    fn from(error: ParseError) -> Self {
+        EPubError::Parse(error)
+    }
+}
+
 impl<IO> From<Utf8Error> for EPubError<IO>
 where
     IO: ReadWriteSeek,
@@ -88,6 +193,31 @@ where
     }
 }
 
+// written out so that only the IO error, not the IO type, has to be Debug
+impl<IO> core::fmt::Debug for EPubError<IO>
+where
+    IO: ReadWriteSeek,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EPubError::InvalidXml => f.write_str("InvalidXml"),
+            EPubError::InvalidLocalHeader => f.write_str("InvalidLocalHeader"),
+            EPubError::Unimplemented => f.write_str("Unimplemented"),
+            EPubError::EPubFileNotExpanded => f.write_str("EPubFileNotExpanded"),
+            EPubError::FormatError(s) => f.debug_tuple("FormatError").field(s).finish(),
+            EPubError::NoSuchVolume => f.write_str("NoSuchVolume"),
+            EPubError::InvalidWorkspace => f.write_str("InvalidWorkspace"),
+            EPubError::Decompress(e) => f.debug_tuple("Decompress").field(e).finish(),
+            EPubError::IO(e) => f.debug_tuple("IO").field(e).finish(),
+            EPubError::UTF8(e) => f.debug_tuple("UTF8").field(e).finish(),
+            EPubError::FromUTF8(e) => f.debug_tuple("FromUTF8").field(e).finish(),
+            EPubError::XmlParseErr(e) => f.debug_tuple("XmlParseErr").field(e).finish(),
+            EPubError::Href(e) => f.debug_tuple("Href").field(e).finish(),
+            EPubError::Parse(e) => f.debug_tuple("Parse").field(e).finish(),
+        }
+    }
+}
+
 impl<IO> From<FromUtf8Error> for EPubError<IO>
 where
     IO: ReadWriteSeek,
@@ -124,12 +254,35 @@ pub struct EPubFile {
     container: Option<Container>,
     package: Option<Package>,
     toc: Option<Toc>,
+    parse_mode: ParseMode,
 }
 
 impl EPubFile {
     pub const CUR_BOOK_DIR: &'static str = "CUR_BOOK";
     pub const EXPAND_DIR: &'static str = "/expanded";
     pub const EPUB_FILE_MEMO: &'static str = "/epub_file.txt";
+    pub const BOOKS_DIR: &'static str = "BOOKS";
+    pub const LIBRARY_INDEX: &'static str = "LIBRARY.TXT";
+
+    /// create the standard layout on a freshly formatted volume
+    ///
+    /// the current book directory, the books directory and an empty library
+    /// index. Anything already there is left alone.
+    pub fn create_layout<IO, TP, OCC>(fs: &mut FileSystem<IO, TP, OCC>) -> Result<(), EPubError<IO>>
+    where
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+    {
+        io::create_dirs(EPubFile::CUR_BOOK_DIR, fs)?;
+        io::create_dirs(EPubFile::BOOKS_DIR, fs)?;
+        let root_dir = fs.root_dir();
+        if root_dir.open_file(EPubFile::LIBRARY_INDEX).is_err() {
+            root_dir.create_file(EPubFile::LIBRARY_INDEX)?;
+        }
+        info!("created library layout");
+        Ok(())
+    }
 
     /// create EPubFile with a filename path
     pub fn new(epub_filepath: &str, expanded_filepath: &str) -> EPubFile {
@@ -142,9 +295,18 @@ impl EPubFile {
             container,
             package,
             toc,
+            parse_mode: ParseMode::Strict,
         }
     }
 
+    /// set how malformed elements are handled when the book is read
+    ///
+    /// in `ParseMode::Lenient` they are skipped and recorded in
+    /// `Package::warnings`
+    pub fn set_parse_mode(&mut self, mode: ParseMode) {
+        self.parse_mode = mode;
+    }
+
     pub fn get_package<'a, IO, TP, OCC>(
         &'a mut self,
         fs: &mut FileSystem<IO, TP, OCC>,
@@ -155,7 +317,9 @@ impl EPubFile {
         OCC: OemCpConverter,
     {
         self.read_container(fs)?;
-        Ok(self.package.as_ref().unwrap())
+        self.package
+            .as_ref()
+            .ok_or(EPubError::FormatError("no package document"))
     }
 
     pub fn get_toc<'a, IO, TP, OCC>(
@@ -168,7 +332,76 @@ impl EPubFile {
         OCC: OemCpConverter,
     {
         self.read_container(fs)?;
-        Ok(self.toc.as_ref().unwrap())
+        self.toc
+            .as_ref()
+            .ok_or(EPubError::FormatError("no table of contents"))
+    }
+
+    /// the landmarks of the book, from the navigation document or else the
+    /// EPUB 2 guide
+    pub fn landmarks<'a, IO, TP, OCC>(
+        &'a mut self,
+        fs: &mut FileSystem<IO, TP, OCC>,
+    ) -> Result<&'a [Landmark], EPubError<IO>>
+    where
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+    {
+        self.read_container(fs)?;
+        if let Some(toc) = &self.toc {
+            if !toc.landmarks.is_empty() {
+                return Ok(&toc.landmarks);
+            }
+        }
+        match &self.package {
+            Some(pkg) => Ok(&pkg.guide),
+            None => Ok(&[]),
+        }
+    }
+
+    /// where to open the book when it hasn't been read yet, skipping the
+    /// cover and other front pages
+    pub fn start_location<IO, TP, OCC>(
+        &mut self,
+        fs: &mut FileSystem<IO, TP, OCC>,
+    ) -> Result<Option<SpinePosition>, EPubError<IO>>
+    where
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+    {
+        self.read_container(fs)?;
+        let pkg = match &self.package {
+            Some(pkg) => pkg,
+            None => return Ok(None),
+        };
+        let landmarks = match &self.toc {
+            Some(toc) if !toc.landmarks.is_empty() => &toc.landmarks,
+            _ => &pkg.guide,
+        };
+        Ok(pkg.start_position(landmarks))
+    }
+
+    /// the cover image of the book, see `Package::cover`
+    pub fn cover<IO, TP, OCC>(
+        &mut self,
+        fs: &mut FileSystem<IO, TP, OCC>,
+    ) -> Result<Option<Cover>, EPubError<IO>>
+    where
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+    {
+        self.read_container(fs)?;
+        let pkg = match &self.package {
+            Some(pkg) => pkg,
+            None => return Ok(None),
+        };
+        // the guide can name a cover page the nav landmarks leave out
+        let nav = self.toc.iter().flat_map(|toc| toc.landmarks.iter());
+        let landmarks: Vec<Landmark> = nav.chain(pkg.guide.iter()).cloned().collect();
+        Ok(pkg.cover(&landmarks, fs))
     }
 
     /// check if epub file has already been expanded
@@ -176,6 +409,21 @@ impl EPubFile {
         &self,
         fs: &mut FileSystem<IO, TP, OCC>,
     ) -> Result<bool, EPubError<IO>>
+    where
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+    {
+        self.has_expanded_with(fs, &mut io::heap_blocks())
+    }
+
+    /// check if epub file has already been expanded, using the caller's
+    /// `BufReader` blocks
+    pub fn has_expanded_with<IO, TP, OCC, const N: usize>(
+        &self,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        blocks: &mut [[u8; N]; 2],
+    ) -> Result<bool, EPubError<IO>>
     where
         IO: ReadWriteSeek,
         TP: TimeProvider,
@@ -186,7 +434,7 @@ impl EPubFile {
         let mut retval = false;
         match root_dir.open_file(&epub_file_memo_name) {
             Ok(f) => {
-                let mut rdr = BufReader::new(f)?;
+                let mut rdr = BufReader::with_blocks(f, blocks)?;
                 let lines = rdr.read_lines()?;
                 for ln in lines {
                     if ln == self.epub_filepath {
@@ -203,11 +451,28 @@ impl EPubFile {
 
     /// expand the epub file into a directory
     ///
-    /// uses 64k of memory while expanding the file, in Container::expand
+    /// allocates a `DefaultWorkspace` on the heap while expanding the file
     pub fn expand<'a, IO, TP, OCC>(
         &mut self,
         fs: &mut FileSystem<IO, TP, OCC>,
     ) -> Result<(), EPubError<IO>>
+    where
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+    {
+        let mut ws = DefaultWorkspace::boxed();
+        self.expand_with(fs, &mut ws)
+    }
+
+    /// expand the epub file into a directory, using the buffers in the workspace
+    ///
+    /// no heap memory is used for the buffers
+    pub fn expand_with<IO, TP, OCC, const WINDOW: usize, const INPUT: usize, const BLOCK: usize>(
+        &mut self,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        ws: &mut Workspace<WINDOW, INPUT, BLOCK>,
+    ) -> Result<(), EPubError<IO>>
     where
         IO: ReadWriteSeek,
         TP: TimeProvider,
@@ -225,7 +490,7 @@ impl EPubFile {
         );
         self.container = Some(Container::new(&container_filepath));
         if let Some(con) = &mut self.container {
-            con.expand(&self.epub_filepath, fs)?;
+            con.expand_with(&self.epub_filepath, fs, ws)?;
             // write a file with the epub filepath in it
             let root_dir = fs.root_dir();
             let file_marker_path = String::from(&self.expanded_filepath) + EPubFile::EPUB_FILE_MEMO;
@@ -248,13 +513,28 @@ impl EPubFile {
         TP: TimeProvider,
         OCC: OemCpConverter,
     {
-        if self.package.is_some() && self.toc.is_some() {
+        self.read_container_with(fs, &mut io::heap_blocks())
+    }
+
+    /// read the container metadata from the epub, using the caller's
+    /// `BufReader` blocks, such as the blocks of a `Workspace`
+    pub fn read_container_with<IO, TP, OCC, const N: usize>(
+        &mut self,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        blocks: &mut [[u8; N]; 2],
+    ) -> Result<(), EPubError<IO>>
+    where
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+    {
+        if self.package.is_some() {
             Ok(())
         } else {
             match &self.container {
                 Some(_) => (),
                 None => {
-                    if self.has_expanded(fs)? {
+                    if self.has_expanded_with(fs, blocks)? {
                         let container_filepath =
                             String::from(&self.expanded_filepath) + EPubFile::EXPAND_DIR;
                         self.container = Some(Container::new(&container_filepath));
@@ -264,25 +544,97 @@ impl EPubFile {
                 }
             }
             let con = self.container.as_ref().unwrap();
-            let res = con.get_container_rootfile(fs)?;
+            let res = con.get_container_rootfile_with(fs, blocks)?;
             if let Some(root_file) = &res {
                 trace!("Found root_file: {:?}", root_file);
-                let pkg = Package::read(&root_file.full_path, fs)?;
+                let pkg = Package::read_with_blocks(root_file, self.parse_mode, fs, blocks)?;
                 info!("Package read: {:?}", pkg);
-                let tocfile = &pkg.spine.toc;
-                for item in &pkg.manifest.items {
-                    if &item.id == tocfile {
-                        let tocitem = item;
-                        let tocpath = String::from(&pkg.base_dir) + "/" + &tocitem.href;
-                        let toc = Toc::read(&tocpath, fs)?;
-                        info!("Toc read: {:?}", toc);
-                        self.toc = Some(toc);
-                        break;
+                // prefer the navigation document, but fall back to the NCX
+                // if it can't be read or has no entries
+                let mut toc = None;
+                if let Some(item) = pkg.manifest.nav_item() {
+                    toc = EPubFile::read_toc(
+                        &pkg,
+                        TocSource::Nav(item),
+                        self.parse_mode,
+                        fs,
+                        blocks,
+                    )?;
+                }
+                if !matches!(&toc, Some(t) if t.has_entries()) {
+                    if let Some(item) = pkg.ncx_item() {
+                        if let Some(ncx) = EPubFile::read_toc(
+                            &pkg,
+                            TocSource::Ncx(item),
+                            self.parse_mode,
+                            fs,
+                            blocks,
+                        )? {
+                            if ncx.has_entries() || toc.is_none() {
+                                toc = Some(ncx);
+                            }
+                        }
                     }
                 }
+                let mut toc = toc.unwrap_or_else(|| {
+                    warn!("No table of contents found");
+                    Toc::empty(&pkg.path)
+                });
+                if !toc.has_entries() {
+                    warn!("Table of contents is empty, building it from the spine");
+                    toc.synthesize_with_blocks(&pkg, fs, blocks);
+                }
+                info!("Toc read: {:?}", toc);
+                self.toc = Some(toc);
                 self.package = Some(pkg);
             }
             Ok(())
         }
     }
+
+    /// read the toc from `source` and find where its entries are in the spine
+    ///
+    /// `None` if the toc has a malformed element in lenient mode, any other
+    /// error, such as a failing device, is returned
+    fn read_toc<IO, TP, OCC, const N: usize>(
+        pkg: &Package,
+        source: TocSource,
+        parse_mode: ParseMode,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        blocks: &mut [[u8; N]; 2],
+    ) -> Result<Option<Toc>, EPubError<IO>>
+    where
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+    {
+        let item = match source {
+            TocSource::Nav(item) | TocSource::Ncx(item) => item,
+        };
+        let toc = match pkg.resolve(&item.href) {
+            Ok(tochref) => match source {
+                TocSource::Nav(_) => {
+                    Toc::read_nav_with_blocks(&pkg.root_dir, &tochref.path, fs, blocks)
+                }
+                TocSource::Ncx(_) => {
+                    Toc::read_with_blocks(&pkg.root_dir, &tochref.path, fs, blocks)
+                }
+            },
+            Err(e) => Err(EPubError::Parse(
+                ParseError::invalid_href("item", "href", e)
+                    .in_file(&io::join_path(&pkg.root_dir, &pkg.path)),
+            )),
+        };
+        match toc {
+            Ok(mut toc) => {
+                toc.resolve_positions(pkg);
+                Ok(Some(toc))
+            }
+            Err(EPubError::Parse(e)) if parse_mode == ParseMode::Lenient => {
+                warn!("Skipping malformed table of contents: {:?}", e);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
 }
@@ -1,6 +1,7 @@
-use crate::EPubError;
+use crate::{io, EPubError};
+use alloc::string::String;
 use byteorder::{ByteOrder, LittleEndian};
-use fatfs::{File, OemCpConverter, Read, ReadWriteSeek, TimeProvider};
+use fatfs::{File, OemCpConverter, ReadWriteSeek, Seek, SeekFrom, TimeProvider};
 
 //use log::{info, trace};
 
@@ -13,7 +14,7 @@ pub struct Block {
 impl Block {
     pub const LEN: usize = 512;
 
-    pub const LEN_U32: u32 = 521;
+    pub const LEN_U32: u32 = 512;
 
     pub fn new() -> Block {
         Block {
@@ -65,81 +66,1079 @@ pub struct Partition {
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct BlockCount(pub u32);
 
-/* Constants for type of partitions, not used here
-/// Marker for a FAT32 partition. Sometimes also use for FAT16 formatted
-/// partitions.
-const PARTITION_ID_FAT32_LBA: u8 = 0x0C;
-/// Marker for a FAT16 partition with LBA. Seen on a Raspberry Pi SD card.
-const PARTITION_ID_FAT16_LBA: u8 = 0x0E;
+/// Marker for an unused partition entry
+pub const PARTITION_ID_EMPTY: u8 = 0x00;
+/// Marker for a FAT12 partition
+pub const PARTITION_ID_FAT12: u8 = 0x01;
+/// Marker for a FAT16 partition smaller than 32MB
+pub const PARTITION_ID_FAT16_SMALL: u8 = 0x04;
+/// Marker for an extended partition, holding a chain of logical partitions
+pub const PARTITION_ID_EXTENDED: u8 = 0x05;
 /// Marker for a FAT16 partition. Seen on a card formatted with the official
 /// SD-Card formatter.
-const PARTITION_ID_FAT16: u8 = 0x06;
+pub const PARTITION_ID_FAT16: u8 = 0x06;
+/// Marker for an exFAT partition. NTFS uses the same marker.
+pub const PARTITION_ID_EXFAT: u8 = 0x07;
 /// Marker for a FAT32 partition. What Macosx disk utility (and also SD-Card formatter?)
 /// use.
-const PARTITION_ID_FAT32_CHS_LBA: u8 = 0x0B;
- */
+pub const PARTITION_ID_FAT32_CHS_LBA: u8 = 0x0B;
+/// Marker for a FAT32 partition. Sometimes also use for FAT16 formatted
+/// partitions.
+pub const PARTITION_ID_FAT32_LBA: u8 = 0x0C;
+/// Marker for a FAT16 partition with LBA. Seen on a Raspberry Pi SD card.
+pub const PARTITION_ID_FAT16_LBA: u8 = 0x0E;
+/// Marker for an extended partition with LBA
+pub const PARTITION_ID_EXTENDED_LBA: u8 = 0x0F;
+/// Marker for the partition covering a GPT disk in the protective MBR
+pub const PARTITION_ID_GPT_PROTECTIVE: u8 = 0xEE;
+/// Marker for an EFI system partition, which is always FAT
+pub const PARTITION_ID_EFI_SYSTEM: u8 = 0xEF;
+/// Marker for a partition that holds no filesystem we know of
+pub const PARTITION_ID_NON_FS_DATA: u8 = 0xDA;
+
+const PARTITION1_START: usize = 446;
+const PARTITION_INFO_LENGTH: usize = 16;
+const PARTITION_INFO_STATUS_INDEX: usize = 0;
+const PARTITION_INFO_TYPE_INDEX: usize = 4;
+const PARTITION_INFO_LBA_START_INDEX: usize = 8;
+const PARTITION_INFO_NUM_BLOCKS_INDEX: usize = 12;
+const FOOTER_START: usize = 510;
+const FOOTER_VALUE: u16 = 0xAA55;
+const DISK_ID_START: usize = 440;
+/// CHS address meaning "use the LBA fields"
+const CHS_LBA_ONLY: [u8; 3] = [0xFE, 0xFF, 0xFF];
+/// stop following a chain of extended boot records after this many
+const MAX_LOGICAL_VOLUMES: usize = 64;
+
+/// What a partition holds, from the partition type marker
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PartitionKind {
+    Empty,
+    Fat12,
+    Fat16,
+    Fat32,
+    ExFat,
+    Extended,
+    GptProtective,
+    EfiSystem,
+    Unknown(u8),
+}
+
+impl PartitionKind {
+    /// classify a partition type marker
+    pub fn from_id(id: u8) -> PartitionKind {
+        match id {
+            PARTITION_ID_EMPTY => PartitionKind::Empty,
+            PARTITION_ID_FAT12 => PartitionKind::Fat12,
+            PARTITION_ID_FAT16_SMALL | PARTITION_ID_FAT16 | PARTITION_ID_FAT16_LBA => {
+                PartitionKind::Fat16
+            }
+            PARTITION_ID_FAT32_CHS_LBA | PARTITION_ID_FAT32_LBA => PartitionKind::Fat32,
+            PARTITION_ID_EXFAT => PartitionKind::ExFat,
+            PARTITION_ID_EXTENDED | PARTITION_ID_EXTENDED_LBA => PartitionKind::Extended,
+            PARTITION_ID_GPT_PROTECTIVE => PartitionKind::GptProtective,
+            PARTITION_ID_EFI_SYSTEM => PartitionKind::EfiSystem,
+            id => PartitionKind::Unknown(id),
+        }
+    }
+
+    /// can the partition be mounted as a FAT volume
+    pub fn is_fat(&self) -> bool {
+        matches!(
+            self,
+            PartitionKind::Fat12
+                | PartitionKind::Fat16
+                | PartitionKind::Fat32
+                | PartitionKind::EfiSystem
+        )
+    }
+}
+
+impl Partition {
+    /// classify the partition by its type marker
+    pub fn kind(&self) -> PartitionKind {
+        PartitionKind::from_id(self.part_type)
+    }
+
+    /// write the partition as one of the four entries of an MBR or EBR block
+    pub fn write_entry(&self, block: &mut Block, entry_idx: usize) {
+        let start = PARTITION1_START + entry_idx * PARTITION_INFO_LENGTH;
+        let entry = &mut block.contents[start..start + PARTITION_INFO_LENGTH];
+        entry[PARTITION_INFO_STATUS_INDEX] = 0x00;
+        // the CHS addresses are not used, they get the maximum as usual for LBA
+        entry[1..4].copy_from_slice(&CHS_LBA_ONLY);
+        entry[PARTITION_INFO_TYPE_INDEX] = self.part_type;
+        entry[5..8].copy_from_slice(&CHS_LBA_ONLY);
+        LittleEndian::write_u32(
+            &mut entry[PARTITION_INFO_LBA_START_INDEX..PARTITION_INFO_LBA_START_INDEX + 4],
+            self.lba_start.0,
+        );
+        LittleEndian::write_u32(
+            &mut entry[PARTITION_INFO_NUM_BLOCKS_INDEX..PARTITION_INFO_NUM_BLOCKS_INDEX + 4],
+            self.num_blocks.0,
+        );
+    }
+}
+
+/// create a Master Boot Record holding up to four partitions
+pub fn mbr_block(partitions: &[Partition], disk_id: u32) -> Block {
+    let mut block = Block::new();
+    LittleEndian::write_u32(
+        &mut block.contents[DISK_ID_START..DISK_ID_START + 4],
+        disk_id,
+    );
+    for (i, p) in partitions.iter().take(4).enumerate() {
+        p.write_entry(&mut block, i);
+    }
+    LittleEndian::write_u16(
+        &mut block.contents[FOOTER_START..FOOTER_START + 2],
+        FOOTER_VALUE,
+    );
+    block
+}
+
+/// check the boot signature of an MBR or EBR block
+fn check_signature<IO: ReadWriteSeek>(block: &Block) -> Result<(), EPubError<IO>> {
+    if LittleEndian::read_u16(&block.contents[FOOTER_START..FOOTER_START + 2]) != FOOTER_VALUE {
+        return Err(EPubError::<IO>::FormatError("Invalid MBR signature"));
+    }
+    Ok(())
+}
+
+/// parse one of the four partition entries in an MBR or EBR block
+///
+/// the start of the partition is as stored, relative to whatever the table
+/// it came from is relative to
+pub fn parse_partition_entry<IO: ReadWriteSeek>(
+    block: &Block,
+    entry_idx: usize,
+) -> Result<Partition, EPubError<IO>> {
+    if entry_idx > 3 {
+        return Err(EPubError::<IO>::NoSuchVolume);
+    }
+    let start = PARTITION1_START + entry_idx * PARTITION_INFO_LENGTH;
+    let partition = &block.contents[start..start + PARTITION_INFO_LENGTH];
+    // Only 0x80 and 0x00 are valid (bootable, and non-bootable)
+    if (partition[PARTITION_INFO_STATUS_INDEX] & 0x7F) != 0x00 {
+        return Err(EPubError::<IO>::FormatError("Invalid partition status"));
+    }
+    let lba_start = LittleEndian::read_u32(
+        &partition[PARTITION_INFO_LBA_START_INDEX..(PARTITION_INFO_LBA_START_INDEX + 4)],
+    );
+    let num_blocks = LittleEndian::read_u32(
+        &partition[PARTITION_INFO_NUM_BLOCKS_INDEX..(PARTITION_INFO_NUM_BLOCKS_INDEX + 4)],
+    );
+    Ok(Partition {
+        part_type: partition[PARTITION_INFO_TYPE_INDEX],
+        lba_start: BlockIdx(lba_start),
+        num_blocks: BlockCount(num_blocks),
+    })
+}
+
+/// add a relative block offset to a block index, checking for overflow
+fn offset_block<IO: ReadWriteSeek>(
+    base: BlockIdx,
+    offset: BlockIdx,
+) -> Result<BlockIdx, EPubError<IO>> {
+    match base.0.checked_add(offset.0) {
+        Some(idx) => Ok(BlockIdx(idx)),
+        None => Err(EPubError::<IO>::FormatError("Partition beyond end of disk")),
+    }
+}
 
 /// Get a volume (or partition) based on entries in the Master Boot
-/// Record. We do not support GUID Partition Table disks. Nor do we
-/// support any concept of drive letters - that is for a higher layer to
-/// handle.
+/// Record. `VolumeIdx(0)` to `VolumeIdx(3)` are the primary partitions,
+/// `VolumeIdx(4)` onwards are the logical partitions inside an extended
+/// partition, in the order of the chain of extended boot records.
+///
+/// GUID Partition Table disks are handled by `get_gpt_partition`,
+/// and `find_partition` picks the right one. We do not support any concept
+/// of drive letters - that is for a higher layer to handle.
 pub fn get_partition<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
     file: &mut File<IO, TP, OCC>,
     volume_idx: VolumeIdx,
 ) -> Result<Partition, EPubError<IO>> {
-    const PARTITION1_START: usize = 446;
-    const PARTITION2_START: usize = PARTITION1_START + PARTITION_INFO_LENGTH;
-    const PARTITION3_START: usize = PARTITION2_START + PARTITION_INFO_LENGTH;
-    const PARTITION4_START: usize = PARTITION3_START + PARTITION_INFO_LENGTH;
-    const FOOTER_START: usize = 510;
-    const FOOTER_VALUE: u16 = 0xAA55;
-    const PARTITION_INFO_LENGTH: usize = 16;
-    const PARTITION_INFO_STATUS_INDEX: usize = 0;
-    const PARTITION_INFO_TYPE_INDEX: usize = 4;
-    const PARTITION_INFO_LBA_START_INDEX: usize = 8;
-    const PARTITION_INFO_NUM_BLOCKS_INDEX: usize = 12;
-
-    let mut block: [u8; 512] = [0u8; 512];
-    file.read(&mut block)?;
-    let (part_type, lba_start, num_blocks) = {
-        // We only support Master Boot Record (MBR) partitioned cards, not
-        // GUID Partition Table (GPT)
-        if LittleEndian::read_u16(&block[FOOTER_START..FOOTER_START + 2]) != FOOTER_VALUE {
-            return Err(EPubError::<IO>::FormatError("Invalid MBR signature"));
-        }
-        let partition = match volume_idx {
-            VolumeIdx(0) => &block[PARTITION1_START..(PARTITION1_START + PARTITION_INFO_LENGTH)],
-            VolumeIdx(1) => &block[PARTITION2_START..(PARTITION2_START + PARTITION_INFO_LENGTH)],
-            VolumeIdx(2) => &block[PARTITION3_START..(PARTITION3_START + PARTITION_INFO_LENGTH)],
-            VolumeIdx(3) => &block[PARTITION4_START..(PARTITION4_START + PARTITION_INFO_LENGTH)],
-            _ => {
-                return Err(EPubError::<IO>::NoSuchVolume);
+    mbr_partition(&mut |idx, block| read_block(file, idx, block), volume_idx)
+}
+
+/// Get a volume from the Master Boot Record, reading blocks with `read_block`
+pub(crate) fn mbr_partition<IO, F>(
+    read_block: &mut F,
+    volume_idx: VolumeIdx,
+) -> Result<Partition, EPubError<IO>>
+where
+    IO: ReadWriteSeek,
+    F: FnMut(BlockIdx, &mut Block) -> Result<(), EPubError<IO>>,
+{
+    let mut block = Block::new();
+    read_block(BlockIdx(0), &mut block)?;
+    check_signature(&block)?;
+    if volume_idx.0 < 4 {
+        return parse_partition_entry(&block, volume_idx.0);
+    }
+    // look for the logical volume in the extended partition
+    let extended = (0..4)
+        .filter_map(|i| parse_partition_entry::<IO>(&block, i).ok())
+        .find(|p| p.kind() == PartitionKind::Extended)
+        .ok_or(EPubError::<IO>::NoSuchVolume)?;
+    let mut ebr_idx = extended.lba_start;
+    let mut logical_idx = 4;
+    for _ in 0..MAX_LOGICAL_VOLUMES {
+        read_block(ebr_idx, &mut block)?;
+        check_signature(&block)?;
+        // the first entry is relative to this EBR, the link to the next EBR
+        // is relative to the start of the extended partition
+        let logical = parse_partition_entry(&block, 0)?;
+        let next = parse_partition_entry(&block, 1)?;
+        if logical.kind() != PartitionKind::Empty {
+            if logical_idx == volume_idx.0 {
+                return Ok(Partition {
+                    lba_start: offset_block(ebr_idx, logical.lba_start)?,
+                    ..logical
+                });
             }
+            logical_idx += 1;
+        }
+        if next.kind() != PartitionKind::Extended || next.lba_start.0 == 0 {
+            break;
+        }
+        ebr_idx = offset_block(extended.lba_start, next.lba_start)?;
+    }
+    Err(EPubError::<IO>::NoSuchVolume)
+}
+
+/// The kinds of partition table found on a disk
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PartitionTable {
+    /// Master Boot Record
+    Mbr,
+    /// GUID Partition Table, behind a protective MBR
+    Gpt,
+}
+
+/// A GUID, as stored on disk
+///
+/// The first three fields are little endian, the rest are bytes in order
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// an unused partition entry
+    pub const EMPTY: Guid = Guid([0; 16]);
+    /// Microsoft basic data partition, EBD0A0A2-B9E5-4433-87C0-68B6B72699C7,
+    /// used for FAT and exFAT volumes
+    pub const BASIC_DATA: Guid = Guid([
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99,
+        0xC7,
+    ]);
+    /// EFI system partition, C12A7328-F81F-11D2-BA4B-00A0C93EC93B, always FAT
+    pub const EFI_SYSTEM: Guid = Guid([
+        0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9,
+        0x3B,
+    ]);
+
+    /// read a guid from the start of a slice
+    pub fn from_slice(data: &[u8]) -> Guid {
+        let mut g = [0u8; 16];
+        g.copy_from_slice(&data[..16]);
+        Guid(g)
+    }
+}
+
+impl core::fmt::Debug for Guid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let g = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            LittleEndian::read_u32(&g[0..4]),
+            LittleEndian::read_u16(&g[4..6]),
+            LittleEndian::read_u16(&g[6..8]),
+            g[8],
+            g[9]
+        )?;
+        for b in &g[10..16] {
+            write!(f, "{:02X}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// The GPT header, found at LBA 1 with a backup in the last block of the disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GptHeader {
+    pub current_lba: u64,
+    pub backup_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: Guid,
+    /// first block of the partition entry array
+    pub entries_lba: u64,
+    pub num_entries: u32,
+    pub entry_size: u32,
+    /// crc32 of the partition entry array
+    pub entries_crc32: u32,
+}
+
+impl GptHeader {
+    const SIGNATURE: &'static [u8] = b"EFI PART";
+    const MIN_HEADER_SIZE: usize = 92;
+    const CRC_INDEX: usize = 16;
+    const REVISION: u32 = 0x0001_0000;
+    /// number of entries in the partition entry arrays we create
+    pub const NUM_ENTRIES: u32 = 128;
+    /// blocks taken by the partition entry arrays we create
+    pub const ENTRIES_BLOCKS: u32 = GptHeader::NUM_ENTRIES * GptEntry::MIN_LEN as u32 / 512;
+
+    /// create the primary, or the backup, header for a disk of `total` blocks
+    /// with a partition entry array of `NUM_ENTRIES`
+    pub fn new(total: BlockCount, disk_guid: Guid, entries_crc32: u32, backup: bool) -> GptHeader {
+        let last = u64::from(total.0) - 1;
+        let (current_lba, backup_lba, entries_lba) = if backup {
+            (last, 1, last - u64::from(GptHeader::ENTRIES_BLOCKS))
+        } else {
+            (1, last, 2)
         };
-        // Only 0x80 and 0x00 are valid (bootable, and non-bootable)
-        if (partition[PARTITION_INFO_STATUS_INDEX] & 0x7F) != 0x00 {
-            return Err(EPubError::<IO>::FormatError("Invalid partition status"));
+        GptHeader {
+            current_lba,
+            backup_lba,
+            first_usable_lba: 2 + u64::from(GptHeader::ENTRIES_BLOCKS),
+            last_usable_lba: last - 1 - u64::from(GptHeader::ENTRIES_BLOCKS),
+            disk_guid,
+            entries_lba,
+            num_entries: GptHeader::NUM_ENTRIES,
+            entry_size: GptEntry::MIN_LEN as u32,
+            entries_crc32,
         }
-        let lba_start = LittleEndian::read_u32(
-            &partition[PARTITION_INFO_LBA_START_INDEX..(PARTITION_INFO_LBA_START_INDEX + 4)],
-        );
-        let num_blocks = LittleEndian::read_u32(
-            &partition[PARTITION_INFO_NUM_BLOCKS_INDEX..(PARTITION_INFO_NUM_BLOCKS_INDEX + 4)],
-        );
-        (
-            partition[PARTITION_INFO_TYPE_INDEX],
-            BlockIdx(lba_start),
-            BlockCount(num_blocks),
-        )
-    };
-    Ok(Partition {
-        part_type,
-        lba_start,
-        num_blocks,
+    }
+
+    /// the header as a block, with the header crc
+    pub fn to_block(&self) -> Block {
+        let mut block = Block::new();
+        let b = &mut block.contents;
+        b[0..8].copy_from_slice(GptHeader::SIGNATURE);
+        LittleEndian::write_u32(&mut b[8..12], GptHeader::REVISION);
+        LittleEndian::write_u32(&mut b[12..16], GptHeader::MIN_HEADER_SIZE as u32);
+        LittleEndian::write_u64(&mut b[24..32], self.current_lba);
+        LittleEndian::write_u64(&mut b[32..40], self.backup_lba);
+        LittleEndian::write_u64(&mut b[40..48], self.first_usable_lba);
+        LittleEndian::write_u64(&mut b[48..56], self.last_usable_lba);
+        b[56..72].copy_from_slice(&self.disk_guid.0);
+        LittleEndian::write_u64(&mut b[72..80], self.entries_lba);
+        LittleEndian::write_u32(&mut b[80..84], self.num_entries);
+        LittleEndian::write_u32(&mut b[84..88], self.entry_size);
+        LittleEndian::write_u32(&mut b[88..92], self.entries_crc32);
+        let crc = crc32(0, &b[0..GptHeader::MIN_HEADER_SIZE]);
+        LittleEndian::write_u32(&mut b[GptHeader::CRC_INDEX..GptHeader::CRC_INDEX + 4], crc);
+        block
+    }
+
+    /// parse a GPT header read from block `lba`, checking the signature,
+    /// the header crc and that the header says it is at `lba`
+    pub fn parse<IO: ReadWriteSeek>(
+        block: &Block,
+        lba: BlockIdx,
+    ) -> Result<GptHeader, EPubError<IO>> {
+        let b = &block.contents;
+        if &b[0..8] != GptHeader::SIGNATURE {
+            return Err(EPubError::FormatError("Invalid GPT signature"));
+        }
+        let header_size = LittleEndian::read_u32(&b[12..16]) as usize;
+        if !(GptHeader::MIN_HEADER_SIZE..=Block::LEN).contains(&header_size) {
+            return Err(EPubError::FormatError("Invalid GPT header size"));
+        }
+        let crc = LittleEndian::read_u32(&b[GptHeader::CRC_INDEX..GptHeader::CRC_INDEX + 4]);
+        // the crc is calculated with the crc field zeroed
+        let mut sum = crc32(0, &b[0..GptHeader::CRC_INDEX]);
+        sum = crc32(sum, &[0; 4]);
+        sum = crc32(sum, &b[GptHeader::CRC_INDEX + 4..header_size]);
+        if sum != crc {
+            return Err(EPubError::FormatError("Invalid GPT header crc"));
+        }
+        let current_lba = LittleEndian::read_u64(&b[24..32]);
+        if current_lba != u64::from(lba.0) {
+            return Err(EPubError::FormatError("GPT header is not at its own block"));
+        }
+        let entry_size = LittleEndian::read_u32(&b[84..88]);
+        if entry_size < GptEntry::MIN_LEN as u32
+            || entry_size > Block::LEN_U32
+            || entry_size % 8 != 0
+        {
+            return Err(EPubError::FormatError("Invalid GPT partition entry size"));
+        }
+        // the array is read and checked whole, so keep it to the usual size
+        let num_entries = LittleEndian::read_u32(&b[80..84]);
+        if num_entries > GptHeader::NUM_ENTRIES {
+            return Err(EPubError::FormatError("Too many GPT partition entries"));
+        }
+        Ok(GptHeader {
+            current_lba,
+            backup_lba: LittleEndian::read_u64(&b[32..40]),
+            first_usable_lba: LittleEndian::read_u64(&b[40..48]),
+            last_usable_lba: LittleEndian::read_u64(&b[48..56]),
+            disk_guid: Guid::from_slice(&b[56..72]),
+            entries_lba: LittleEndian::read_u64(&b[72..80]),
+            num_entries,
+            entry_size,
+            entries_crc32: LittleEndian::read_u32(&b[88..92]),
+        })
+    }
+}
+
+/// A partition entry from the GPT partition entry array
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GptEntry {
+    pub type_guid: Guid,
+    pub unique_guid: Guid,
+    pub first_lba: u64,
+    /// last block of the partition, inclusive
+    pub last_lba: u64,
+    pub attributes: u64,
+    pub name: String,
+}
+
+impl GptEntry {
+    const MIN_LEN: usize = 128;
+
+    /// parse a partition entry
+    pub fn parse(data: &[u8]) -> GptEntry {
+        let name_units = data[56..GptEntry::MIN_LEN]
+            .chunks(2)
+            .map(LittleEndian::read_u16)
+            .take_while(|c| *c != 0);
+        let name = core::char::decode_utf16(name_units)
+            .map(|r| r.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect();
+        GptEntry {
+            type_guid: Guid::from_slice(&data[0..16]),
+            unique_guid: Guid::from_slice(&data[16..32]),
+            first_lba: LittleEndian::read_u64(&data[32..40]),
+            last_lba: LittleEndian::read_u64(&data[40..48]),
+            attributes: LittleEndian::read_u64(&data[48..56]),
+            name,
+        }
+    }
+
+    /// write the entry into the start of `data`
+    pub fn write(&self, data: &mut [u8]) {
+        data[0..16].copy_from_slice(&self.type_guid.0);
+        data[16..32].copy_from_slice(&self.unique_guid.0);
+        LittleEndian::write_u64(&mut data[32..40], self.first_lba);
+        LittleEndian::write_u64(&mut data[40..48], self.last_lba);
+        LittleEndian::write_u64(&mut data[48..56], self.attributes);
+        for b in data[56..GptEntry::MIN_LEN].iter_mut() {
+            *b = 0;
+        }
+        // the name is 36 UTF-16 code units at most
+        for (i, c) in self.name.encode_utf16().take(36).enumerate() {
+            LittleEndian::write_u16(&mut data[56 + i * 2..58 + i * 2], c);
+        }
+    }
+
+    /// is this entry unused
+    pub fn is_empty(&self) -> bool {
+        self.type_guid == Guid::EMPTY
+    }
+
+    /// the MBR marker closest to the type guid
+    ///
+    /// a basic data partition may hold FAT12, FAT16, FAT32 or exFAT, it is
+    /// given the FAT32 marker and the boot sector tells which
+    pub fn part_type(&self) -> u8 {
+        if self.type_guid == Guid::EMPTY {
+            PARTITION_ID_EMPTY
+        } else if self.type_guid == Guid::BASIC_DATA {
+            PARTITION_ID_FAT32_LBA
+        } else if self.type_guid == Guid::EFI_SYSTEM {
+            PARTITION_ID_EFI_SYSTEM
+        } else {
+            PARTITION_ID_NON_FS_DATA
+        }
+    }
+
+    /// the entry as a `Partition`, with `part_type` from the type guid
+    ///
+    /// partitions that do not fit in 32 bit block numbers are an error
+    pub fn partition<IO: ReadWriteSeek>(&self) -> Result<Partition, EPubError<IO>> {
+        if self.last_lba < self.first_lba || self.last_lba >= u64::from(u32::MAX) {
+            return Err(EPubError::FormatError("GPT partition out of range"));
+        }
+        Ok(Partition {
+            part_type: self.part_type(),
+            lba_start: BlockIdx(self.first_lba as u32),
+            num_blocks: BlockCount((self.last_lba - self.first_lba + 1) as u32),
+        })
+    }
+}
+
+/// is the block a protective MBR, in front of a GUID Partition Table
+pub fn is_protective_mbr(block: &Block) -> bool {
+    if LittleEndian::read_u16(&block.contents[FOOTER_START..FOOTER_START + 2]) != FOOTER_VALUE {
+        return false;
+    }
+    (0..4).any(|i| {
+        block.contents[PARTITION1_START + i * PARTITION_INFO_LENGTH + PARTITION_INFO_TYPE_INDEX]
+            == PARTITION_ID_GPT_PROTECTIVE
     })
 }
 
+/// find out which partition table the disk has
+pub fn detect_partition_table<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    file: &mut File<IO, TP, OCC>,
+) -> Result<PartitionTable, EPubError<IO>> {
+    let mut block = Block::new();
+    read_block(file, BlockIdx(0), &mut block)?;
+    if is_protective_mbr(&block) {
+        Ok(PartitionTable::Gpt)
+    } else {
+        Ok(PartitionTable::Mbr)
+    }
+}
+
+/// Get a volume (or partition), from either an MBR or GPT partitioned disk
+pub fn find_partition<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    file: &mut File<IO, TP, OCC>,
+    volume_idx: VolumeIdx,
+) -> Result<Partition, EPubError<IO>> {
+    match detect_partition_table(file)? {
+        PartitionTable::Mbr => get_partition(file, volume_idx),
+        PartitionTable::Gpt => get_gpt_partition(file, volume_idx),
+    }
+}
+
+/// Read the GPT header, falling back to the backup header at the end of
+/// the disk if the primary is damaged
+pub fn read_gpt_header<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    file: &mut File<IO, TP, OCC>,
+) -> Result<GptHeader, EPubError<IO>> {
+    let last = last_block(file)?;
+    gpt_header(&mut |idx, block| read_block(file, idx, block), last)
+}
+
+/// Get a volume (or partition) based on the entries of the GUID Partition
+/// Table. `VolumeIdx(n)` is the n'th entry in the partition entry array.
+pub fn get_gpt_partition<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    file: &mut File<IO, TP, OCC>,
+    volume_idx: VolumeIdx,
+) -> Result<Partition, EPubError<IO>> {
+    get_gpt_entry(file, volume_idx)?.partition()
+}
+
+/// Get an entry from the GUID Partition Table, after checking the crc of
+/// the partition entry array.
+pub fn get_gpt_entry<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    file: &mut File<IO, TP, OCC>,
+    volume_idx: VolumeIdx,
+) -> Result<GptEntry, EPubError<IO>> {
+    let last = last_block(file)?;
+    gpt_entry(
+        &mut |idx, block| read_block(file, idx, block),
+        last,
+        volume_idx,
+    )
+}
+
+/// Read the GPT header with `read_block`. `last` is the last block of the
+/// disk, where the backup header is, if known
+pub(crate) fn gpt_header<IO, F>(
+    read_block: &mut F,
+    last: Option<BlockIdx>,
+) -> Result<GptHeader, EPubError<IO>>
+where
+    IO: ReadWriteSeek,
+    F: FnMut(BlockIdx, &mut Block) -> Result<(), EPubError<IO>>,
+{
+    let mut block = Block::new();
+    read_block(BlockIdx(1), &mut block)?;
+    match GptHeader::parse(&block, BlockIdx(1)) {
+        Ok(hdr) => Ok(hdr),
+        Err(e) => match last {
+            Some(last) if last > BlockIdx(1) => {
+                log::warn!("primary GPT header is damaged, trying the backup");
+                read_block(last, &mut block)?;
+                GptHeader::parse(&block, last)
+            }
+            _ => Err(e),
+        },
+    }
+}
+
+/// Get an entry from the GUID Partition Table, reading blocks with `read_block`
+pub(crate) fn gpt_entry<IO, F>(
+    read_block: &mut F,
+    last: Option<BlockIdx>,
+    volume_idx: VolumeIdx,
+) -> Result<GptEntry, EPubError<IO>>
+where
+    IO: ReadWriteSeek,
+    F: FnMut(BlockIdx, &mut Block) -> Result<(), EPubError<IO>>,
+{
+    let hdr = gpt_header(read_block, last)?;
+    let entry_data = match gpt_entry_data(read_block, &hdr, volume_idx)? {
+        Some(data) => data,
+        // the primary array is damaged, so use the backup header and array
+        None => match last {
+            Some(last) if hdr.current_lba == 1 && last > BlockIdx(1) => {
+                log::warn!("primary GPT partition entries are damaged, trying the backup");
+                let mut block = Block::new();
+                read_block(last, &mut block)?;
+                let backup = GptHeader::parse(&block, last)?;
+                gpt_entry_data(read_block, &backup, volume_idx)?
+                    .ok_or(EPubError::FormatError("Invalid GPT partition entries crc"))?
+            }
+            _ => return Err(EPubError::FormatError("Invalid GPT partition entries crc")),
+        },
+    };
+    let entry = GptEntry::parse(&entry_data);
+    if entry.is_empty() {
+        return Err(EPubError::NoSuchVolume);
+    }
+    Ok(entry)
+}
+
+/// Read entry `volume_idx` from the partition entry array of `hdr`, or
+/// `None` if the crc of the array doesn't match the header
+fn gpt_entry_data<IO, F>(
+    read_block: &mut F,
+    hdr: &GptHeader,
+    volume_idx: VolumeIdx,
+) -> Result<Option<[u8; GptEntry::MIN_LEN]>, EPubError<IO>>
+where
+    IO: ReadWriteSeek,
+    F: FnMut(BlockIdx, &mut Block) -> Result<(), EPubError<IO>>,
+{
+    if volume_idx.0 >= hdr.num_entries as usize {
+        return Err(EPubError::NoSuchVolume);
+    }
+    // `GptHeader::parse` bounds the array to `NUM_ENTRIES` entries of at
+    // most a block each
+    let entry_size = hdr.entry_size as usize;
+    let array_len = hdr.num_entries as usize * entry_size;
+    let array_blocks = array_len.div_ceil(Block::LEN) as u64;
+    if hdr.entries_lba + array_blocks > u64::from(u32::MAX) {
+        return Err(EPubError::FormatError("GPT partition entries out of range"));
+    }
+    let wanted = volume_idx.0 * entry_size;
+    let mut entry_data = [0u8; GptEntry::MIN_LEN];
+    let mut sum = 0;
+    let mut block = Block::new();
+    let mut idx = BlockIdx(hdr.entries_lba as u32);
+    let mut offset = 0;
+    // walk the whole array for the crc, keeping the wanted entry
+    while offset < array_len {
+        read_block(idx, &mut block)?;
+        let n = core::cmp::min(Block::LEN, array_len - offset);
+        sum = crc32(sum, &block.contents[..n]);
+        // copy the part of the wanted entry that is in this block
+        let start = core::cmp::max(offset, wanted);
+        let end = core::cmp::min(offset + n, wanted + GptEntry::MIN_LEN);
+        if start < end {
+            entry_data[start - wanted..end - wanted]
+                .copy_from_slice(&block.contents[start - offset..end - offset]);
+        }
+        offset += n;
+        idx += BlockCount(1);
+    }
+    if sum != hdr.entries_crc32 {
+        return Ok(None);
+    }
+    Ok(Some(entry_data))
+}
+
+/// Write a GUID Partition Table with up to `GptHeader::NUM_ENTRIES` partitions
+/// on a disk of `total` blocks. The protective MBR, the primary and the backup
+/// tables are all written with `write_block`.
+pub(crate) fn write_gpt<IO, F>(
+    write_block: &mut F,
+    total: BlockCount,
+    disk_guid: Guid,
+    entries: &[GptEntry],
+) -> Result<(), EPubError<IO>>
+where
+    IO: ReadWriteSeek,
+    F: FnMut(BlockIdx, &Block) -> Result<(), EPubError<IO>>,
+{
+    if total.0 < 3 + 2 * GptHeader::ENTRIES_BLOCKS
+        || entries.len() > GptHeader::NUM_ENTRIES as usize
+    {
+        return Err(EPubError::FormatError("Disk too small for a GPT"));
+    }
+    // an entry array block holds the entries from i * per_block
+    let per_block = Block::LEN / GptEntry::MIN_LEN;
+    let entries_block = |i: usize| {
+        let mut block = Block::new();
+        for (j, e) in entries
+            .iter()
+            .skip(i * per_block)
+            .take(per_block)
+            .enumerate()
+        {
+            e.write(&mut block.contents[j * GptEntry::MIN_LEN..(j + 1) * GptEntry::MIN_LEN]);
+        }
+        block
+    };
+    let nblocks = GptHeader::ENTRIES_BLOCKS as usize;
+    let sum = (0..nblocks).fold(0, |sum, i| crc32(sum, &entries_block(i).contents));
+    let protective = Partition {
+        part_type: PARTITION_ID_GPT_PROTECTIVE,
+        lba_start: BlockIdx(1),
+        num_blocks: BlockCount(total.0 - 1),
+    };
+    write_block(BlockIdx(0), &mbr_block(&[protective], 0))?;
+    for backup in [false, true].iter() {
+        let hdr = GptHeader::new(total, disk_guid, sum, *backup);
+        for i in 0..nblocks {
+            let idx = BlockIdx(hdr.entries_lba as u32 + i as u32);
+            write_block(idx, &entries_block(i))?;
+        }
+        write_block(BlockIdx(hdr.current_lba as u32), &hdr.to_block())?;
+    }
+    Ok(())
+}
+
+/// the last block of the disk, if the disk is big enough to hold a GPT
+fn last_block<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    file: &mut File<IO, TP, OCC>,
+) -> Result<Option<BlockIdx>, EPubError<IO>> {
+    let blocks = file.seek(SeekFrom::End(0))? / Block::LEN as u64;
+    if blocks < 3 || blocks - 1 > u64::from(u32::MAX) {
+        Ok(None)
+    } else {
+        Ok(Some(BlockIdx((blocks - 1) as u32)))
+    }
+}
+
+/// read a block from the disk
+fn read_block<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    file: &mut File<IO, TP, OCC>,
+    idx: BlockIdx,
+    block: &mut Block,
+) -> Result<(), EPubError<IO>> {
+    file.seek(SeekFrom::Start(idx.into_bytes()))?;
+    if io::read_full(file, &mut block.contents)? != Block::LEN {
+        return Err(EPubError::IO(fatfs::Error::UnexpectedEof));
+    }
+    Ok(())
+}
+
+/// update a crc32 (IEEE 802.3, as used by GPT and zip) with more data
+///
+/// start with a crc of 0
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for b in data {
+        crc ^= u32::from(*b);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
 #[cfg(test)]
 mod tests {
-    //    use super::*;
+    use super::*;
+    use fatfs::StdIoWrapper;
+
+    type IO = StdIoWrapper<std::fs::File>;
+
+    /// build a GPT header block with a valid crc
+    fn gpt_header(entries_crc32: u32) -> Block {
+        let mut block = Block::new();
+        let b = &mut block.contents;
+        b[0..8].copy_from_slice(b"EFI PART");
+        LittleEndian::write_u32(&mut b[8..12], 0x0001_0000);
+        LittleEndian::write_u32(&mut b[12..16], 92);
+        LittleEndian::write_u64(&mut b[24..32], 1);
+        LittleEndian::write_u64(&mut b[32..40], 2047);
+        LittleEndian::write_u64(&mut b[40..48], 34);
+        LittleEndian::write_u64(&mut b[48..56], 2014);
+        LittleEndian::write_u64(&mut b[72..80], 2);
+        LittleEndian::write_u32(&mut b[80..84], 128);
+        LittleEndian::write_u32(&mut b[84..88], 128);
+        LittleEndian::write_u32(&mut b[88..92], entries_crc32);
+        let crc = crc32(0, &b[0..92]);
+        LittleEndian::write_u32(&mut b[16..20], crc);
+        block
+    }
+
+    /// write a partition entry into an MBR or EBR block
+    fn set_entry(block: &mut Block, i: usize, part_type: u8, start: u32, len: u32) {
+        let e = &mut block.contents[446 + i * 16..446 + (i + 1) * 16];
+        e[4] = part_type;
+        LittleEndian::write_u32(&mut e[8..12], start);
+        LittleEndian::write_u32(&mut e[12..16], len);
+    }
+
+    fn boot_block() -> Block {
+        let mut block = Block::new();
+        block.contents[510] = 0x55;
+        block.contents[511] = 0xAA;
+        block
+    }
+
+    /// a disk with a FAT32 primary, and an extended partition at block 1000
+    /// holding two logical volumes
+    fn extended_disk() -> alloc::vec::Vec<(u32, Block)> {
+        let mut mbr = boot_block();
+        set_entry(&mut mbr, 0, PARTITION_ID_FAT32_LBA, 8, 992);
+        set_entry(&mut mbr, 1, PARTITION_ID_EXTENDED_LBA, 1000, 3000);
+        let mut ebr1 = boot_block();
+        set_entry(&mut ebr1, 0, PARTITION_ID_FAT16, 8, 992);
+        set_entry(&mut ebr1, 1, PARTITION_ID_EXTENDED, 1000, 2000);
+        let mut ebr2 = boot_block();
+        set_entry(&mut ebr2, 0, PARTITION_ID_FAT32_LBA, 8, 1992);
+        alloc::vec![(0, mbr), (1000, ebr1), (2000, ebr2)]
+    }
+
+    fn read_from(
+        disk: &[(u32, Block)],
+    ) -> impl FnMut(BlockIdx, &mut Block) -> Result<(), EPubError<IO>> + '_ {
+        move |idx, block| {
+            *block = match disk.iter().find(|(i, _)| *i == idx.0) {
+                Some((_, b)) => b.clone(),
+                None => Block::new(),
+            };
+            Ok(())
+        }
+    }
+
+    fn get(disk: &[(u32, Block)], idx: usize) -> Option<Partition> {
+        mbr_partition(&mut read_from(disk), VolumeIdx(idx)).ok()
+    }
+
+    #[test]
+    fn test_logical_partitions() {
+        let disk = extended_disk();
+        let p0 = get(&disk, 0).unwrap();
+        assert_eq!(p0.kind(), PartitionKind::Fat32);
+        assert_eq!(p0.lba_start, BlockIdx(8));
+        assert_eq!(get(&disk, 1).unwrap().kind(), PartitionKind::Extended);
+        assert_eq!(get(&disk, 2).unwrap().kind(), PartitionKind::Empty);
+        let p4 = get(&disk, 4).unwrap();
+        assert_eq!(p4.kind(), PartitionKind::Fat16);
+        assert_eq!(p4.lba_start, BlockIdx(1008));
+        assert_eq!(p4.num_blocks, BlockCount(992));
+        let p5 = get(&disk, 5).unwrap();
+        assert_eq!(p5.kind(), PartitionKind::Fat32);
+        assert_eq!(p5.lba_start, BlockIdx(2008));
+        assert_eq!(p5.num_blocks, BlockCount(1992));
+        assert!(get(&disk, 6).is_none());
+    }
+
+    #[test]
+    fn test_no_extended_partition() {
+        let mut mbr = boot_block();
+        set_entry(&mut mbr, 0, PARTITION_ID_FAT16_LBA, 8, 992);
+        let disk = alloc::vec![(0, mbr)];
+        assert!(get(&disk, 0).unwrap().kind().is_fat());
+        assert!(get(&disk, 4).is_none());
+    }
+
+    #[test]
+    fn test_ebr_loop() {
+        let mut mbr = boot_block();
+        set_entry(&mut mbr, 0, PARTITION_ID_EXTENDED_LBA, 100, 200);
+        let mut ebr = boot_block();
+        set_entry(&mut ebr, 0, PARTITION_ID_FAT12, 1, 10);
+        set_entry(&mut ebr, 1, PARTITION_ID_EXTENDED, 50, 100);
+        // the second EBR, 50 blocks into the extended partition, links back to itself
+        let mut looped = boot_block();
+        set_entry(&mut looped, 0, PARTITION_ID_FAT12, 1, 10);
+        set_entry(&mut looped, 1, PARTITION_ID_EXTENDED, 50, 100);
+        let disk = alloc::vec![(0, mbr), (100, ebr), (150, looped)];
+        assert_eq!(get(&disk, 4).unwrap().lba_start, BlockIdx(101));
+        assert_eq!(get(&disk, 5).unwrap().lba_start, BlockIdx(151));
+        // the looping EBR repeats until the walk gives up
+        assert!(get(&disk, 3 + MAX_LOGICAL_VOLUMES).is_some());
+        let mut reads = 0;
+        let mut read = read_from(&disk);
+        let result = mbr_partition(
+            &mut |idx, block: &mut Block| {
+                reads += 1;
+                read(idx, block)
+            },
+            VolumeIdx(4 + MAX_LOGICAL_VOLUMES),
+        );
+        assert!(result.is_err());
+        // the MBR, then one read for each EBR visited
+        assert_eq!(reads, 1 + MAX_LOGICAL_VOLUMES);
+    }
+
+    #[test]
+    fn test_partition_kind() {
+        assert_eq!(PartitionKind::from_id(0x00), PartitionKind::Empty);
+        assert_eq!(PartitionKind::from_id(0x01), PartitionKind::Fat12);
+        assert_eq!(PartitionKind::from_id(0x0E), PartitionKind::Fat16);
+        assert_eq!(PartitionKind::from_id(0x0B), PartitionKind::Fat32);
+        assert_eq!(PartitionKind::from_id(0x07), PartitionKind::ExFat);
+        assert_eq!(PartitionKind::from_id(0x0F), PartitionKind::Extended);
+        assert_eq!(PartitionKind::from_id(0x83), PartitionKind::Unknown(0x83));
+        assert!(!PartitionKind::ExFat.is_fat());
+    }
+
+    #[test]
+    fn test_mbr_block_roundtrip() {
+        let p = Partition {
+            part_type: PARTITION_ID_FAT32_LBA,
+            lba_start: BlockIdx(2048),
+            num_blocks: BlockCount(100_000),
+        };
+        let block = mbr_block(&[p], 0x1234_5678);
+        assert!(!is_protective_mbr(&block));
+        let disk = alloc::vec![(0, block)];
+        assert_eq!(get(&disk, 0), Some(p));
+        assert_eq!(get(&disk, 1).unwrap().kind(), PartitionKind::Empty);
+    }
+
+    #[test]
+    fn test_gpt_roundtrip() {
+        let total = BlockCount(4096);
+        let entry = GptEntry {
+            type_guid: Guid::BASIC_DATA,
+            unique_guid: Guid([7; 16]),
+            first_lba: 2048,
+            last_lba: 4062,
+            attributes: 0,
+            name: alloc::string::String::from("EPUB"),
+        };
+        let mut disk: alloc::vec::Vec<(u32, Block)> = alloc::vec::Vec::new();
+        let res = write_gpt::<IO, _>(
+            &mut |idx, block| {
+                disk.push((idx.0, block.clone()));
+                Ok(())
+            },
+            total,
+            Guid([9; 16]),
+            &[entry.clone()],
+        );
+        assert!(res.is_ok());
+        let (_, mbr) = disk.iter().find(|(i, _)| *i == 0).unwrap();
+        assert!(is_protective_mbr(mbr));
+        #[derive(Clone, Copy)]
+        enum Damage {
+            None,
+            Header,
+            Entries,
+            // the backup header where the primary should be, with a good crc
+            Moved,
+        }
+        let read = |damage: Damage| {
+            let mut rd = read_from(&disk);
+            move |idx: BlockIdx, block: &mut Block| {
+                match damage {
+                    Damage::Moved if idx == BlockIdx(1) => rd(BlockIdx(4095), block)?,
+                    _ => rd(idx, block)?,
+                }
+                match damage {
+                    Damage::Header if idx == BlockIdx(1) => block.contents[0] = 0,
+                    Damage::Entries if idx == BlockIdx(2) => block.contents[0] ^= 0xFF,
+                    _ => {}
+                }
+                Ok(())
+            }
+        };
+        let last = Some(BlockIdx(total.0 - 1));
+        let damages = [Damage::None, Damage::Header, Damage::Entries, Damage::Moved];
+        for damage in damages.iter() {
+            let e = gpt_entry(&mut read(*damage), last, VolumeIdx(0)).unwrap();
+            assert_eq!(e, entry);
+            assert!(gpt_entry(&mut read(*damage), last, VolumeIdx(1)).is_err());
+        }
+        // without the backup a damaged array is an error
+        assert!(gpt_entry(&mut read(Damage::Entries), None, VolumeIdx(0)).is_err());
+        let hdr = super::gpt_header(&mut read(Damage::Moved), last).unwrap();
+        assert_eq!(hdr.current_lba, 4095);
+        let hdr = super::gpt_header(&mut read(Damage::Header), last).unwrap();
+        assert_eq!(hdr.current_lba, 4095);
+        assert_eq!(hdr.entries_lba, 4095 - 32);
+        assert_eq!(hdr.first_usable_lba, 34);
+        assert_eq!(hdr.last_usable_lba, 4062);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_gpt_header() {
+        let mut block = gpt_header(0x1234_5678);
+        let hdr = GptHeader::parse::<IO>(&block, BlockIdx(1)).unwrap();
+        assert_eq!(hdr.current_lba, 1);
+        assert_eq!(hdr.backup_lba, 2047);
+        assert_eq!(hdr.entries_lba, 2);
+        assert_eq!(hdr.num_entries, 128);
+        assert_eq!(hdr.entry_size, 128);
+        assert_eq!(hdr.entries_crc32, 0x1234_5678);
+        // a good header read from another block
+        assert!(GptHeader::parse::<IO>(&block, BlockIdx(2047)).is_err());
+        // corrupt it
+        block.contents[40] = 0xFF;
+        assert!(GptHeader::parse::<IO>(&block, BlockIdx(1)).is_err());
+        // entry arrays that are too large, with a good crc
+        for (num_entries, entry_size) in [(129, 128), (128, 1024), (u32::MAX, 128)].iter() {
+            let mut block = gpt_header(0);
+            let b = &mut block.contents;
+            LittleEndian::write_u32(&mut b[80..84], *num_entries);
+            LittleEndian::write_u32(&mut b[84..88], *entry_size);
+            LittleEndian::write_u32(&mut b[16..20], 0);
+            let crc = crc32(0, &b[0..92]);
+            LittleEndian::write_u32(&mut b[16..20], crc);
+            assert!(GptHeader::parse::<IO>(&block, BlockIdx(1)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_gpt_entry() {
+        let mut data = [0u8; 128];
+        data[0..16].copy_from_slice(&Guid::BASIC_DATA.0);
+        LittleEndian::write_u64(&mut data[32..40], 2048);
+        LittleEndian::write_u64(&mut data[40..48], 4095);
+        for (i, c) in "books".encode_utf16().enumerate() {
+            LittleEndian::write_u16(&mut data[56 + i * 2..58 + i * 2], c);
+        }
+        let entry = GptEntry::parse(&data);
+        assert_eq!(entry.type_guid, Guid::BASIC_DATA);
+        assert_eq!(entry.name, "books");
+        assert!(!entry.is_empty());
+        let part = entry.partition::<IO>().unwrap();
+        assert_eq!(part.lba_start, BlockIdx(2048));
+        assert_eq!(part.num_blocks, BlockCount(2048));
+        assert_eq!(part.kind(), PartitionKind::Fat32);
+        data[0..16].copy_from_slice(&Guid::EFI_SYSTEM.0);
+        let part = GptEntry::parse(&data).partition::<IO>().unwrap();
+        assert_eq!(part.kind(), PartitionKind::EfiSystem);
+        assert!(part.kind().is_fat());
+        data[0..16].copy_from_slice(&[0x42; 16]);
+        let part = GptEntry::parse(&data).partition::<IO>().unwrap();
+        assert_eq!(
+            part.kind(),
+            PartitionKind::Unknown(PARTITION_ID_NON_FS_DATA)
+        );
+        assert!(!part.kind().is_fat());
+        assert!(GptEntry::parse(&[0u8; 128]).is_empty());
+    }
+
+    #[test]
+    fn test_guid_format() {
+        assert_eq!(
+            alloc::format!("{:?}", Guid::BASIC_DATA),
+            "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7"
+        );
+    }
+
+    #[test]
+    fn test_protective_mbr() {
+        let mut block = Block::new();
+        block.contents[446 + 4] = PARTITION_ID_GPT_PROTECTIVE;
+        assert!(!is_protective_mbr(&block));
+        block.contents[510] = 0x55;
+        block.contents[511] = 0xAA;
+        assert!(is_protective_mbr(&block));
+        block.contents[446 + 4] = 0x0C;
+        assert!(!is_protective_mbr(&block));
+    }
 }
@@ -1,8 +1,12 @@
 //! the EPub Navigation Document
 //! https://www.w3.org/publishing/epub32/epub-packages.html#sec-package-nav
 
-use crate::{io::BufReader, package::Meta, EPubError};
-use alloc::{string::String, vec::Vec};
+use crate::{
+    io::{self, Href, HrefError},
+    package::{Landmark, LandmarkType, Meta, Package, SpinePosition, OPS_NS, XML_NS},
+    EPubError, ParseError, ParseErrorKind,
+};
+use alloc::{string::String, vec, vec::Vec};
 use fatfs::{FileSystem, OemCpConverter, ReadWriteSeek, TimeProvider};
 use log::{info, trace, warn};
 use xml::{Event, Parser, StartTag};
@@ -11,93 +15,213 @@ use xml::{Event, Parser, StartTag};
 #[derive(Debug)]
 pub struct Toc {
     pub meta_entries: Vec<Meta>,
+    /// the `dtb:` entries of `meta_entries`
+    pub dtb: DtbMeta,
     pub doc_title: String,
+    /// the NCX `docAuthor` entries
+    pub doc_authors: Vec<String>,
     pub nav_points: Vec<NavPoint>,
+    /// the print pages, from the NCX `pageList` or the `page-list` nav
+    pub page_list: Vec<PageTarget>,
+    /// the NCX `navList` entries, such as a list of illustrations
+    pub nav_lists: Vec<NavList>,
+    /// the `landmarks` nav of an EPUB 3 navigation document
+    pub landmarks: Vec<Landmark>,
+    /// path of the toc file relative to the container root
+    pub path: String,
 }
 
 impl Toc {
-    /// read the package data from the file
+    /// read the NCX toc from the file at `toc_path`, relative to the container root `container_dir`
     pub fn read<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
-        toc_file_name: &str,
+        container_dir: &str,
+        toc_path: &str,
+        fs: &mut FileSystem<IO, TP, OCC>,
+    ) -> Result<Toc, EPubError<IO>> {
+        Toc::read_with_blocks(container_dir, toc_path, fs, &mut io::heap_blocks())
+    }
+
+    /// read the NCX toc like `read`, using the caller's `BufReader` blocks
+    pub fn read_with_blocks<
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+        const N: usize,
+    >(
+        container_dir: &str,
+        toc_path: &str,
         fs: &mut FileSystem<IO, TP, OCC>,
+        blocks: &mut [[u8; N]; 2],
+    ) -> Result<Toc, EPubError<IO>> {
+        let toc_file_name = io::join_path(container_dir, toc_path);
+        let lines = io::read_file_lines(&toc_file_name, fs, blocks)?;
+        Toc::parse_ncx(&lines, &toc_file_name, toc_path)
+    }
+
+    /// read the toc from the EPUB 3 navigation document at `nav_path`,
+    /// relative to the container root `container_dir`
+    pub fn read_nav<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+        container_dir: &str,
+        nav_path: &str,
+        fs: &mut FileSystem<IO, TP, OCC>,
+    ) -> Result<Toc, EPubError<IO>> {
+        Toc::read_nav_with_blocks(container_dir, nav_path, fs, &mut io::heap_blocks())
+    }
+
+    /// read the navigation document like `read_nav`, using the caller's
+    /// `BufReader` blocks
+    pub fn read_nav_with_blocks<
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+        const N: usize,
+    >(
+        container_dir: &str,
+        nav_path: &str,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        blocks: &mut [[u8; N]; 2],
+    ) -> Result<Toc, EPubError<IO>> {
+        let nav_file_name = io::join_path(container_dir, nav_path);
+        let lines = io::read_file_lines(&nav_file_name, fs, blocks)?;
+        Toc::parse_nav(&lines, &nav_file_name, nav_path)
+    }
+
+    /// parse the lines of an NCX document
+    fn parse_ncx<IO: ReadWriteSeek>(
+        lines: &[String],
+        toc_file_name: &str,
+        toc_path: &str,
     ) -> Result<Toc, EPubError<IO>> {
-        let root_dir = fs.root_dir();
-        // open the file
-        let toc_file = root_dir.open_file(toc_file_name)?;
-        info!("Opened '{}'", toc_file_name);
-        let mut rdr = BufReader::new(toc_file)?;
         let mut p = Parser::new();
-        let lines = rdr.read_lines()?;
         let mut stack: Vec<Event> = Vec::new();
         let mut chars = String::new();
-        let mut in_head = false;
         let mut in_doctitle = false;
+        let mut in_docauthor = false;
         let mut in_navmap = false;
-        let mut in_navpoint = false;
-        let mut nav_point: Option<NavPoint> = None;
+        let mut in_pagelist = false;
+        let mut page_target: Option<PageTarget> = None;
+        let mut page_list: Vec<PageTarget> = Vec::new();
+        let mut nav_list: Option<NavList> = None;
+        let mut nav_target: Option<NavPoint> = None;
+        let mut nav_lists: Vec<NavList> = Vec::new();
+        // the navPoints not yet closed, innermost last
+        let mut open_points: Vec<NavPoint> = Vec::new();
         let mut doc_title = String::new();
+        let mut doc_authors: Vec<String> = Vec::new();
         let mut nav_points: Vec<NavPoint> = Vec::new();
         let mut meta_entries: Vec<Meta> = Vec::new();
+        let mut dtb = DtbMeta::default();
         for ln in lines {
-            p.feed_str(&ln);
+            p.feed_str(ln);
             for event in &mut p {
                 match event {
                     Ok(e) => match e {
                         Event::PI(s) => info!("PI({})", s),
                         Event::ElementStart(tag) => {
                             trace!("Start({})", tag.name);
-                            if tag.name == "head" {
-                                in_head = true;
+                            if !is_ncx(&tag) {
+                                trace!("Skipping element in namespace {:?}", tag.ns);
                             } else if tag.name == "navMap" {
                                 in_navmap = true;
                             } else if tag.name == "navPoint" && in_navmap {
-                                in_navpoint = true;
-                                nav_point = Some(NavPoint::new(&tag)?);
+                                open_points.push(
+                                    NavPoint::new(&tag).map_err(|e| e.in_file(toc_file_name))?,
+                                );
+                            } else if tag.name == "pageList" {
+                                in_pagelist = true;
+                            } else if tag.name == "pageTarget" && in_pagelist {
+                                page_target = Some(PageTarget::new(&tag));
+                            } else if tag.name == "navList" {
+                                nav_list = Some(NavList::new(&tag));
+                            } else if tag.name == "navTarget" && nav_list.is_some() {
+                                nav_target = Some(
+                                    NavPoint::new(&tag).map_err(|e| e.in_file(toc_file_name))?,
+                                );
                             } else if tag.name == "docTitle" {
                                 in_doctitle = true;
+                            } else if tag.name == "docAuthor" {
+                                in_docauthor = true;
                             }
                             stack.push(Event::ElementStart(tag));
                             chars = String::new();
                         }
                         Event::ElementEnd(tag) => {
                             trace!("End({})", tag.name);
-                            if let Some(last) = stack.pop() {
-                                match last {
-                                    Event::ElementStart(start_tag) => {
-                                        if tag.name == "head" {
-                                            in_head = false;
-                                        } else if tag.name == "navMap" {
-                                            in_navmap = false;
-                                        } else if tag.name == "navPoint" {
-                                            in_navpoint = false;
-                                            if let Some(np) = nav_point {
-                                                trace!("Adding navpoint: {:?}", np);
-                                                nav_points.push(np);
-                                                nav_point = None;
-                                            }
-                                        } else if tag.name == "docTitle" {
-                                            in_doctitle = false;
-                                        } else if tag.name == "text" {
-                                            if in_navpoint {
-                                                if let Some(mut np) = nav_point {
-                                                    np.add_label(&chars);
-                                                    nav_point = Some(np);
-                                                }
-                                            } else if in_doctitle {
-                                                doc_title += &chars;
-                                            }
-                                        } else if tag.name == "content" && in_navpoint {
-                                            if let Some(mut np) = nav_point {
-                                                np.add_content::<IO>(&start_tag)?;
-                                                nav_point = Some(np);
-                                            }
-                                        } else if tag.name == "meta" && in_head {
-                                            let m = Meta::new(&start_tag, &chars);
-                                            meta_entries.push(m);
+                            if let Some(Event::ElementStart(start_tag)) = stack.pop() {
+                                if start_tag.name != tag.name {
+                                    return Err(EPubError::Parse(ParseError {
+                                        file: String::from(toc_file_name),
+                                        element: start_tag.name,
+                                        kind: ParseErrorKind::MismatchedEnd(tag.name),
+                                    }));
+                                }
+                                if !is_ncx(&start_tag) {
+                                    continue;
+                                }
+                                if tag.name == "navMap" {
+                                    in_navmap = false;
+                                } else if tag.name == "navPoint" {
+                                    if let Some(np) = open_points.pop() {
+                                        trace!("Adding navpoint: {:?}", np);
+                                        match open_points.last_mut() {
+                                            Some(parent) => parent.children.push(np),
+                                            None => nav_points.push(np),
+                                        }
+                                    }
+                                } else if tag.name == "pageList" {
+                                    in_pagelist = false;
+                                } else if tag.name == "pageTarget" {
+                                    if let Some(pt) = page_target.take() {
+                                        trace!("Adding page target: {:?}", pt);
+                                        page_list.push(pt);
+                                    }
+                                } else if tag.name == "navList" {
+                                    if let Some(nl) = nav_list.take() {
+                                        trace!("Adding nav list: {:?}", nl.label);
+                                        nav_lists.push(nl);
+                                    }
+                                } else if tag.name == "navTarget" {
+                                    if let (Some(nl), Some(nt)) = (&mut nav_list, nav_target.take())
+                                    {
+                                        nl.targets.push(nt);
+                                    }
+                                } else if tag.name == "docTitle" {
+                                    in_doctitle = false;
+                                } else if tag.name == "docAuthor" {
+                                    in_docauthor = false;
+                                } else if tag.name == "text" {
+                                    if let Some(pt) = &mut page_target {
+                                        pt.label += &chars;
+                                    } else if let Some(nt) = &mut nav_target {
+                                        nt.add_label(&chars);
+                                    } else if let Some(np) = open_points.last_mut() {
+                                        np.add_label(&chars);
+                                    } else if let Some(nl) = &mut nav_list {
+                                        nl.label += &chars;
+                                    } else if in_doctitle {
+                                        doc_title += &chars;
+                                    } else if in_docauthor {
+                                        doc_authors.push(chars.clone());
+                                    }
+                                } else if tag.name == "content" {
+                                    if let Some(pt) = &mut page_target {
+                                        if let Some(src) =
+                                            start_tag.attributes.get(&(String::from("src"), None))
+                                        {
+                                            pt.content += src;
                                         }
-                                        assert!(start_tag.name == tag.name);
+                                    } else if let Some(nt) = &mut nav_target {
+                                        nt.add_content(&start_tag)
+                                            .map_err(|e| e.in_file(toc_file_name))?;
+                                    } else if let Some(np) = open_points.last_mut() {
+                                        np.add_content(&start_tag)
+                                            .map_err(|e| e.in_file(toc_file_name))?;
                                     }
-                                    _ => (),
+                                } else if tag.name == "meta" {
+                                    let m = Meta::new(&start_tag, &chars)
+                                        .map_err(|e| e.in_file(toc_file_name))?;
+                                    dtb.add(&m);
+                                    meta_entries.push(m);
                                 }
                             }
                         }
@@ -117,10 +241,754 @@ impl Toc {
         info!("Finished parsing '{}'", toc_file_name);
         Ok(Toc {
             meta_entries,
+            dtb,
             doc_title,
+            doc_authors,
             nav_points,
+            page_list,
+            nav_lists,
+            landmarks: Vec::new(),
+            path: String::from(toc_path),
         })
     }
+
+    /// parse the lines of an EPUB 3 navigation document
+    ///
+    /// the `nav` elements with `epub:type` `toc` and `page-list` are read,
+    /// every `a` or `span` label in them becomes an entry
+    fn parse_nav<IO: ReadWriteSeek>(
+        lines: &[String],
+        nav_file_name: &str,
+        nav_path: &str,
+    ) -> Result<Toc, EPubError<IO>> {
+        let mut p = Parser::new();
+        // the open elements, with the inherited hidden and xml:lang
+        let mut stack: Vec<(String, bool, Option<String>)> = Vec::new();
+        let mut in_title = false;
+        let mut doc_title = String::new();
+        // the kind of nav being read and its depth in the stack
+        let mut nav_kind: Option<(NavKind, usize)> = None;
+        // depth of the label being read
+        let mut label_depth: Option<usize> = None;
+        let mut nav_point: Option<NavPoint> = None;
+        // an entry for each open `li`, filled in once its label is read
+        let mut open_points: Vec<Option<NavPoint>> = Vec::new();
+        let mut play_order = 0;
+        let mut li_id: Option<String> = None;
+        // the entries of the nav being read
+        let mut entries: Vec<NavPoint> = Vec::new();
+        let mut nav_points: Vec<NavPoint> = Vec::new();
+        let mut page_list: Vec<PageTarget> = Vec::new();
+        let mut landmarks: Vec<Landmark> = Vec::new();
+        for ln in lines {
+            p.feed_str(ln);
+            for event in &mut p {
+                match event {
+                    Ok(Event::ElementStart(tag)) => {
+                        trace!("Start({})", tag.name);
+                        let (parent_hidden, parent_lang) = match stack.last() {
+                            Some((_, hidden, lang)) => (*hidden, lang.clone()),
+                            None => (false, None),
+                        };
+                        let hidden = parent_hidden
+                            || tag.attributes.contains_key(&(String::from("hidden"), None));
+                        let lang = tag
+                            .attributes
+                            .get(&(String::from("lang"), Some(String::from(XML_NS))))
+                            .cloned()
+                            .or(parent_lang);
+                        if tag.name == "title" && nav_kind.is_none() {
+                            in_title = true;
+                        } else if tag.name == "nav" && nav_kind.is_none() {
+                            if let Some(t) = tag
+                                .attributes
+                                .get(&(String::from("type"), Some(String::from(OPS_NS))))
+                            {
+                                nav_kind = t
+                                    .split_ascii_whitespace()
+                                    .find_map(NavKind::from_type)
+                                    .map(|k| (k, stack.len()));
+                            }
+                        } else if nav_kind.is_some() && label_depth.is_none() {
+                            if tag.name == "li" {
+                                open_points.push(None);
+                                li_id = tag.attributes.get(&(String::from("id"), None)).cloned();
+                            } else if tag.name == "a" || tag.name == "span" {
+                                label_depth = Some(stack.len());
+                                let id = li_id
+                                    .take()
+                                    .or_else(|| {
+                                        tag.attributes.get(&(String::from("id"), None)).cloned()
+                                    })
+                                    .unwrap_or_default();
+                                let content = tag
+                                    .attributes
+                                    .get(&(String::from("href"), None))
+                                    .cloned()
+                                    .unwrap_or_default();
+                                let epub_type = tag
+                                    .attributes
+                                    .get(&(String::from("type"), Some(String::from(OPS_NS))))
+                                    .cloned();
+                                play_order += 1;
+                                nav_point = Some(NavPoint {
+                                    id,
+                                    play_order,
+                                    label: String::new(),
+                                    content,
+                                    hidden,
+                                    lang: lang.clone(),
+                                    epub_type,
+                                    children: Vec::new(),
+                                    position: None,
+                                    synthesized: false,
+                                });
+                            }
+                        }
+                        stack.push((tag.name, hidden, lang));
+                    }
+                    Ok(Event::ElementEnd(tag)) => {
+                        trace!("End({})", tag.name);
+                        if let Some((name, _, _)) = stack.pop() {
+                            if name != tag.name {
+                                return Err(EPubError::Parse(ParseError {
+                                    file: String::from(nav_file_name),
+                                    element: name,
+                                    kind: ParseErrorKind::MismatchedEnd(tag.name),
+                                }));
+                            }
+                        }
+                        if tag.name == "title" {
+                            in_title = false;
+                        } else if label_depth == Some(stack.len()) {
+                            label_depth = None;
+                            if let Some(mut np) = nav_point.take() {
+                                np.label = collapse_whitespace(&np.label);
+                                match open_points.last_mut() {
+                                    Some(open) if open.is_none() => *open = Some(np),
+                                    _ => warn!("Ignoring second label '{}'", np.label),
+                                }
+                            }
+                        } else if nav_kind.is_some() && tag.name == "li" {
+                            if let Some(Some(np)) = open_points.pop() {
+                                trace!("Adding navpoint: {:?}", np);
+                                // an entry without a label passes its children up
+                                match open_points.iter_mut().rev().find_map(|o| o.as_mut()) {
+                                    Some(parent) => parent.children.push(np),
+                                    None => entries.push(np),
+                                }
+                            }
+                        } else if let Some((kind, depth)) = nav_kind {
+                            if depth == stack.len() {
+                                nav_kind = None;
+                                match kind {
+                                    NavKind::Toc => nav_points.append(&mut entries),
+                                    NavKind::PageList => {
+                                        page_list.extend(
+                                            NavPoints::new(&entries)
+                                                .map(|(_, np)| PageTarget::from_nav_point(np)),
+                                        );
+                                        entries.clear();
+                                    }
+                                    NavKind::Landmarks => {
+                                        for np in NavPoints::new(&entries).map(|(_, np)| np) {
+                                            match Toc::landmark(np, nav_path) {
+                                                Some(l) => landmarks.push(l),
+                                                None => warn!("Skipping landmark '{}'", np.label),
+                                            }
+                                        }
+                                        entries.clear();
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    Ok(Event::Characters(s)) => {
+                        if let Some(np) = &mut nav_point {
+                            np.label += &s;
+                        } else if in_title {
+                            doc_title += &s;
+                        }
+                    }
+                    Ok(Event::CDATA(s)) => {
+                        if let Some(np) = &mut nav_point {
+                            np.label += &s;
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(e) => return Err(EPubError::XmlParseErr(e)),
+                }
+            }
+        }
+        info!("Finished parsing '{}'", nav_file_name);
+        Ok(Toc {
+            meta_entries: Vec::new(),
+            dtb: DtbMeta::default(),
+            doc_title: collapse_whitespace(&doc_title),
+            doc_authors: Vec::new(),
+            nav_points,
+            page_list,
+            nav_lists: Vec::new(),
+            landmarks,
+            path: String::from(nav_path),
+        })
+    }
+
+    /// an empty toc, for a book that has none, `path` is used to resolve hrefs
+    pub fn empty(path: &str) -> Toc {
+        Toc {
+            meta_entries: Vec::new(),
+            dtb: DtbMeta::default(),
+            doc_title: String::new(),
+            doc_authors: Vec::new(),
+            nav_points: Vec::new(),
+            page_list: Vec::new(),
+            nav_lists: Vec::new(),
+            landmarks: Vec::new(),
+            path: String::from(path),
+        }
+    }
+
+    /// is there an entry that points at a spine item
+    pub fn has_entries(&self) -> bool {
+        self.iter().any(|(_, np)| np.position.is_some())
+    }
+
+    /// replace the entries with one for each linear spine item
+    ///
+    /// for books with an empty or broken toc. Each entry is titled with the
+    /// first `h1`-`h3` heading of its document, or else the document's
+    /// `<title>`, or else its file name.
+    pub fn synthesize<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+        &mut self,
+        pkg: &Package,
+        fs: &mut FileSystem<IO, TP, OCC>,
+    ) {
+        self.synthesize_with_blocks(pkg, fs, &mut io::heap_blocks())
+    }
+
+    /// replace the entries like `synthesize`, using the caller's `BufReader`
+    /// blocks to read the documents
+    pub fn synthesize_with_blocks<
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+        const N: usize,
+    >(
+        &mut self,
+        pkg: &Package,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        blocks: &mut [[u8; N]; 2],
+    ) {
+        let mut nav_points = Vec::new();
+        for (index, itemref) in pkg.spine.itemrefs.iter().enumerate() {
+            if !itemref.linear {
+                continue;
+            }
+            let (item, file_name) = match pkg.spine_item(index) {
+                Some(found) => found,
+                None => {
+                    warn!("Spine item '{}' is not in the manifest", itemref.idref);
+                    continue;
+                }
+            };
+            let title = match io::read_file_lines(&file_name, fs, blocks) {
+                Ok(lines) => document_title(&lines),
+                Err(_) => {
+                    warn!("Unable to read '{}' for its title", file_name);
+                    None
+                }
+            };
+            let label = title.unwrap_or_else(|| io::basename_and_ext(&file_name).0);
+            // the manifest href is relative to the package document, the
+            // entry's content has to be relative to the toc
+            let content = match pkg.resolve(&item.href) {
+                Ok(href) => io::relative_href(&self.path, &href.path),
+                Err(_) => continue,
+            };
+            nav_points.push(NavPoint {
+                id: item.id.clone(),
+                play_order: nav_points.len() as u32 + 1,
+                label,
+                content,
+                hidden: false,
+                lang: None,
+                epub_type: None,
+                children: Vec::new(),
+                position: Some(SpinePosition {
+                    index,
+                    fragment: None,
+                }),
+                synthesized: true,
+            });
+        }
+        info!(
+            "Synthesized {} toc entries from the spine",
+            nav_points.len()
+        );
+        self.nav_points = nav_points;
+    }
+
+    /// every entry in document order, with its depth, the top level is 0
+    pub fn iter(&self) -> NavPoints<'_> {
+        NavPoints::new(&self.nav_points)
+    }
+
+    /// the depth of the deepest entry plus one, 0 if there are no entries
+    pub fn depth(&self) -> usize {
+        self.iter().map(|(d, _)| d + 1).max().unwrap_or(0)
+    }
+
+    /// a landmark from an entry of the landmarks nav in the document at `nav_path`
+    fn landmark(np: &NavPoint, nav_path: &str) -> Option<Landmark> {
+        let kind = np.epub_type.as_deref()?.split_ascii_whitespace().next()?;
+        let href = io::resolve_href(nav_path, &np.content).ok()?;
+        Some(Landmark {
+            kind: LandmarkType::from_type(kind),
+            title: np.label.clone(),
+            href,
+        })
+    }
+
+    /// find where each entry and each page of the page list starts in the spine
+    pub fn resolve_positions(&mut self, pkg: &Package) {
+        resolve_nav_points(&mut self.nav_points, &self.path, pkg);
+        for nl in self.nav_lists.iter_mut() {
+            resolve_nav_points(&mut nl.targets, &self.path, pkg);
+        }
+        for pt in self.page_list.iter_mut() {
+            pt.position = match io::resolve_href(&self.path, &pt.content) {
+                Ok(href) => pkg.locate(&href),
+                Err(e) => {
+                    warn!("Page '{}' has a bad target: {:?}", pt.label, e);
+                    None
+                }
+            };
+        }
+    }
+
+    /// the print page with `label`, for "go to page"
+    pub fn page(&self, label: &str) -> Option<&PageTarget> {
+        let label = label.trim();
+        self.page_list.iter().find(|pt| pt.label == label)
+    }
+
+    /// the print page the reader is on at `position`
+    ///
+    /// a page starting at exactly `position`, or else the last page that
+    /// starts in an earlier spine item or at the top of this one. Pages that
+    /// start partway through the current item can only be matched by their
+    /// fragment.
+    pub fn current_page(&self, position: &SpinePosition) -> Option<&PageTarget> {
+        covering(
+            self.page_list.iter().map(|pt| (pt.position.as_ref(), pt)),
+            position,
+        )
+    }
+
+    /// the print page the reader is on at byte `offset` of spine item `index`
+    ///
+    /// matched the same way as `entry_at_offset`
+    pub fn page_at_offset(
+        &self,
+        index: usize,
+        offset: usize,
+        anchors: &[Anchor],
+    ) -> Option<&PageTarget> {
+        covering_offset(
+            self.page_list.iter().map(|pt| (pt.position.as_ref(), pt)),
+            index,
+            offset,
+            anchors,
+        )
+    }
+
+    /// the entry the reader is in at `position`, for the chapter title
+    ///
+    /// matched the same way as `current_page`, the deepest entry wins when
+    /// nested entries start at the same place. Only the fragment of the
+    /// position is known, use `entry_at_offset` for a reader that is
+    /// partway through a spine item.
+    pub fn current_entry(&self, position: &SpinePosition) -> Option<&NavPoint> {
+        covering(
+            self.iter().map(|(_, np)| (np.position.as_ref(), np)),
+            position,
+        )
+    }
+
+    /// the entry the reader is in at byte `offset` of spine item `index`
+    ///
+    /// `anchors` are the ids of the spine item with their offsets, from
+    /// `anchor_offsets`. The last entry starting at or before the offset
+    /// wins, the deepest one when nested entries start at the same place.
+    /// An entry in this spine item whose fragment is not among the anchors
+    /// can't be placed and is skipped.
+    pub fn entry_at_offset(
+        &self,
+        index: usize,
+        offset: usize,
+        anchors: &[Anchor],
+    ) -> Option<&NavPoint> {
+        covering_offset(
+            self.iter().map(|(_, np)| (np.position.as_ref(), np)),
+            index,
+            offset,
+            anchors,
+        )
+    }
+
+    /// the entry with `id`, anywhere in the tree
+    pub fn entry(&self, id: &str) -> Option<&NavPoint> {
+        self.iter().map(|(_, np)| np).find(|np| np.id == id)
+    }
+
+    /// resolve an href found in the toc, such as a `NavPoint` content
+    pub fn resolve(&self, href: &str) -> Result<Href, HrefError> {
+        io::resolve_href(&self.path, href)
+    }
+}
+
+/// find the spine position of each entry, and of the entries nested under it
+fn resolve_nav_points(nav_points: &mut [NavPoint], toc_path: &str, pkg: &Package) {
+    for np in nav_points.iter_mut().filter(|np| !np.synthesized) {
+        np.position = match io::resolve_href(toc_path, &np.content) {
+            Ok(href) => pkg.locate(&href),
+            Err(e) => {
+                warn!("Entry '{}' has a bad target: {:?}", np.label, e);
+                None
+            }
+        };
+        resolve_nav_points(&mut np.children, toc_path, pkg);
+    }
+}
+
+/// the first `h1`-`h3` heading of an XHTML content document, or else its `<title>`
+fn document_title(lines: &[String]) -> Option<String> {
+    let mut p = Parser::new();
+    let mut title = String::new();
+    let mut heading = String::new();
+    // the element whose text is being collected, and the depth it is at
+    let mut collecting: Option<(String, usize)> = None;
+    let mut depth = 0;
+    for ln in lines {
+        p.feed_str(ln);
+        for event in &mut p {
+            match event {
+                Ok(Event::ElementStart(tag)) => {
+                    depth += 1;
+                    let wanted = match tag.name.as_str() {
+                        "title" => title.is_empty(),
+                        "h1" | "h2" | "h3" => true,
+                        _ => false,
+                    };
+                    if wanted && collecting.is_none() {
+                        collecting = Some((tag.name, depth));
+                    }
+                }
+                Ok(Event::ElementEnd(tag)) => {
+                    if let Some((name, d)) = &collecting {
+                        if *d == depth && *name == tag.name {
+                            collecting = None;
+                            if tag.name != "title" {
+                                let h = collapse_whitespace(&heading);
+                                if !h.is_empty() {
+                                    return Some(h);
+                                }
+                                heading.clear();
+                            }
+                        }
+                    }
+                    depth -= 1;
+                }
+                Ok(Event::Characters(s)) | Ok(Event::CDATA(s)) => match &collecting {
+                    Some((name, _)) if name == "title" => title += &s,
+                    Some(_) => heading += &s,
+                    None => (),
+                },
+                Ok(_) => (),
+                Err(e) => {
+                    warn!("Stopped looking for a title: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+    let title = collapse_whitespace(&title);
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+/// the last item, in reading order, that starts at or before `position`
+///
+/// the last item starting at exactly `position` if there is one, otherwise
+/// the last one starting in an earlier spine item or at the top of this one.
+/// Items starting partway through the current spine item can only be matched
+/// by their fragment.
+fn covering<'a, T>(
+    items: impl Iterator<Item = (Option<&'a SpinePosition>, T)>,
+    position: &SpinePosition,
+) -> Option<T> {
+    let mut exact = None;
+    let mut current = None;
+    for (pos, item) in items {
+        if let Some(pos) = pos {
+            if pos == position {
+                exact = Some(item);
+            } else if exact.is_none()
+                && (pos.index < position.index
+                    || (pos.index == position.index && pos.fragment.is_none()))
+            {
+                current = Some(item);
+            }
+        }
+    }
+    exact.or(current)
+}
+
+/// the last item, in reading order, that starts at or before byte `offset`
+/// of spine item `index`, see `Toc::entry_at_offset`
+fn covering_offset<'a, T>(
+    items: impl Iterator<Item = (Option<&'a SpinePosition>, T)>,
+    index: usize,
+    offset: usize,
+    anchors: &[Anchor],
+) -> Option<T> {
+    let mut found: Option<((usize, usize), T)> = None;
+    for (pos, item) in items {
+        let pos = match pos {
+            Some(pos) if pos.index <= index => pos,
+            _ => continue,
+        };
+        // items in earlier spine items keep their reading order
+        let start = match &pos.fragment {
+            Some(fragment) if pos.index == index => {
+                match anchors.iter().find(|a| a.id == *fragment) {
+                    Some(a) => a.offset,
+                    None => continue,
+                }
+            }
+            _ => 0,
+        };
+        let key = (pos.index, start);
+        if key > (index, offset) {
+            continue;
+        }
+        match &found {
+            Some((k, _)) if *k > key => (),
+            _ => found = Some((key, item)),
+        }
+    }
+    found.map(|(_, item)| item)
+}
+
+/// an element with an `id` in a content document, a fragment a toc entry
+/// can point at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    pub id: String,
+    /// byte offset in the document of the line the element's start tag ends on
+    pub offset: usize,
+}
+
+/// the anchors of a content document, in document order
+///
+/// the lines are as read by `BufReader::read_lines`, so the offsets are only
+/// as precise as the line the start tag is on
+pub fn anchor_offsets(lines: &[String]) -> Vec<Anchor> {
+    let mut p = Parser::new();
+    let mut anchors = Vec::new();
+    let mut offset = 0;
+    for ln in lines {
+        p.feed_str(ln);
+        for event in &mut p {
+            match event {
+                Ok(Event::ElementStart(tag)) => {
+                    if let Some(id) = tag.attributes.get(&(String::from("id"), None)) {
+                        anchors.push(Anchor {
+                            id: id.clone(),
+                            offset,
+                        });
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    warn!("Stopped looking for anchors: {:?}", e);
+                    return anchors;
+                }
+            }
+        }
+        offset += ln.len();
+    }
+    anchors
+}
+
+/// trim and replace each run of whitespace with a single space
+fn collapse_whitespace(s: &str) -> String {
+    let mut out = String::new();
+    for word in s.split_whitespace() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out += word;
+    }
+    out
+}
+
+/// namespace of the NCX
+const NCX_NS: &str = "http://www.daisy.org/z3986/2005/ncx/";
+
+/// is the element in the NCX namespace, or in none
+fn is_ncx(tag: &StartTag) -> bool {
+    match &tag.ns {
+        Some(ns) => ns == NCX_NS,
+        None => true,
+    }
+}
+
+/// the typed `dtb:` meta entries from the NCX head
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DtbMeta {
+    /// `dtb:uid`, should match the package identifier
+    pub uid: Option<String>,
+    /// `dtb:depth`, the depth of the navMap
+    pub depth: Option<u32>,
+    /// `dtb:totalPageCount`, 0 if there are no pages
+    pub total_page_count: Option<u32>,
+    /// `dtb:maxPageNumber`, 0 if there are no pages
+    pub max_page_number: Option<u32>,
+}
+
+impl DtbMeta {
+    /// record the meta if it is one of the `dtb:` entries
+    pub fn add(&mut self, meta: &Meta) {
+        let number = || meta.content.trim().parse::<u32>().ok();
+        match meta.name.as_str() {
+            "dtb:uid" => self.uid = Some(String::from(meta.content.trim())),
+            "dtb:depth" => self.depth = number(),
+            "dtb:totalPageCount" => self.total_page_count = number(),
+            "dtb:maxPageNumber" => self.max_page_number = number(),
+            _ => (),
+        }
+    }
+}
+
+/// an NCX `navList`, a flat list of targets such as illustrations or tables
+#[derive(Debug, Clone)]
+pub struct NavList {
+    /// attribute `id`
+    pub id: Option<String>,
+    /// attribute `class`, the kind of list
+    pub class: Option<String>,
+    /// the heading of the list
+    pub label: String,
+    pub targets: Vec<NavPoint>,
+}
+
+impl NavList {
+    /// create a new nav list from the `navList` tag
+    pub fn new(tag: &StartTag) -> NavList {
+        NavList {
+            id: tag.attributes.get(&(String::from("id"), None)).cloned(),
+            class: tag.attributes.get(&(String::from("class"), None)).cloned(),
+            label: String::new(),
+            targets: Vec::new(),
+        }
+    }
+}
+
+/// the `nav` elements of a navigation document that are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NavKind {
+    Toc,
+    PageList,
+    Landmarks,
+}
+
+impl NavKind {
+    /// the kind for a value of `epub:type`
+    fn from_type(epub_type: &str) -> Option<NavKind> {
+        match epub_type {
+            "toc" => Some(NavKind::Toc),
+            "page-list" => Some(NavKind::PageList),
+            "landmarks" => Some(NavKind::Landmarks),
+            _ => None,
+        }
+    }
+}
+
+/// the kind of a print page, attribute `type` of an NCX `pageTarget`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageType {
+    /// front matter, usually roman numerals
+    Front,
+    Normal,
+    /// pages that are neither, such as plates
+    Special,
+}
+
+impl PageType {
+    fn from_attribute(value: &str) -> PageType {
+        match value {
+            "front" => PageType::Front,
+            "special" => PageType::Special,
+            _ => PageType::Normal,
+        }
+    }
+}
+
+/// a print page from the page list
+#[derive(Debug, Clone)]
+pub struct PageTarget {
+    pub id: String,
+    pub page_type: PageType,
+    /// the page number, if it has one
+    pub value: Option<u32>,
+    /// the page label, such as `xii` or `42`
+    pub label: String,
+    /// where the page starts, relative to the toc
+    pub content: String,
+    /// where the page starts in the spine, see `Toc::resolve_positions`
+    pub position: Option<SpinePosition>,
+}
+
+impl PageTarget {
+    /// create a new page target from an NCX `pageTarget` tag
+    pub fn new(tag: &StartTag) -> PageTarget {
+        PageTarget {
+            id: tag
+                .attributes
+                .get(&(String::from("id"), None))
+                .cloned()
+                .unwrap_or_default(),
+            page_type: tag
+                .attributes
+                .get(&(String::from("type"), None))
+                .map(|t| PageType::from_attribute(t))
+                .unwrap_or(PageType::Normal),
+            value: tag
+                .attributes
+                .get(&(String::from("value"), None))
+                .and_then(|v| v.trim().parse::<u32>().ok()),
+            label: String::new(),
+            content: String::new(),
+            position: None,
+        }
+    }
+
+    /// a page target from an entry in a `page-list` nav
+    fn from_nav_point(np: &NavPoint) -> PageTarget {
+        PageTarget {
+            id: np.id.clone(),
+            page_type: PageType::Normal,
+            value: np.label.parse::<u32>().ok(),
+            label: np.label.clone(),
+            content: np.content.clone(),
+            position: None,
+        }
+    }
 }
 
 /// NavPoint from EPub file
@@ -130,34 +998,88 @@ pub struct NavPoint {
     pub play_order: u32,
     pub label: String,
     pub content: String,
+    /// the entry has the `hidden` attribute, or is inside one that does
+    pub hidden: bool,
+    /// the `xml:lang` of the entry, inherited from its ancestors
+    pub lang: Option<String>,
+    /// the `epub:type` of the entry's link, as in the landmarks nav
+    pub epub_type: Option<String>,
+    /// the entries nested under this one
+    pub children: Vec<NavPoint>,
+    /// where the entry points in the spine, see `Toc::resolve_positions`
+    pub position: Option<SpinePosition>,
+    /// the entry was built from the spine by `Toc::synthesize`
+    pub synthesized: bool,
 }
 
-impl NavPoint {
-    pub fn new<IO: ReadWriteSeek>(tag: &StartTag) -> Result<NavPoint, EPubError<IO>> {
-        match tag.attributes.get(&(String::from("id"), None)) {
-            Some(id_val) => match tag.attributes.get(&(String::from("playOrder"), None)) {
-                Some(order_val) => Ok(NavPoint {
-                    id: String::from(id_val),
-                    play_order: order_val.parse::<u32>().unwrap(),
-                    label: String::new(),
-                    content: String::new(),
-                }),
-                None => Err(EPubError::InvalidXml),
-            },
-            None => Err(EPubError::InvalidXml),
+/// depth first iterator over the entries of a `Toc`
+pub struct NavPoints<'a> {
+    stack: Vec<core::slice::Iter<'a, NavPoint>>,
+}
+
+impl<'a> NavPoints<'a> {
+    fn new(nav_points: &'a [NavPoint]) -> NavPoints<'a> {
+        NavPoints {
+            stack: vec![nav_points.iter()],
+        }
+    }
+}
+
+impl<'a> Iterator for NavPoints<'a> {
+    type Item = (usize, &'a NavPoint);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(iter) = self.stack.last_mut() {
+            if let Some(np) = iter.next() {
+                let depth = self.stack.len() - 1;
+                if !np.children.is_empty() {
+                    self.stack.push(np.children.iter());
+                }
+                return Some((depth, np));
+            }
+            self.stack.pop();
         }
+        None
+    }
+}
+
+impl NavPoint {
+    pub fn new(tag: &StartTag) -> Result<NavPoint, ParseError> {
+        let id_val = tag
+            .attributes
+            .get(&(String::from("id"), None))
+            .ok_or_else(|| ParseError::missing_attribute(&tag.name, "id"))?;
+        let order_val = tag
+            .attributes
+            .get(&(String::from("playOrder"), None))
+            .ok_or_else(|| ParseError::missing_attribute(&tag.name, "playOrder"))?;
+        Ok(NavPoint {
+            id: String::from(id_val),
+            play_order: order_val
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| ParseError::invalid_attribute(&tag.name, "playOrder"))?,
+            label: String::new(),
+            content: String::new(),
+            hidden: false,
+            lang: None,
+            epub_type: None,
+            children: Vec::new(),
+            position: None,
+            synthesized: false,
+        })
     }
 
     pub fn add_label(&mut self, label: &str) {
         self.label += label;
     }
 
-    pub fn add_content<IO: ReadWriteSeek>(&mut self, tag: &StartTag) -> Result<(), EPubError<IO>> {
+    pub fn add_content(&mut self, tag: &StartTag) -> Result<(), ParseError> {
         if let Some(content) = tag.attributes.get(&(String::from("src"), None)) {
             self.content += content;
             Ok(())
         } else {
-            Err(EPubError::InvalidXml)
+            Err(ParseError::missing_attribute(&tag.name, "src"))
         }
     }
 }
@@ -189,7 +1111,7 @@ mod tests {
                         trace!("attribute '{}:{:?}' is '{}'", key1, key2, val);
                     }
                     if tag.name == "navPoint" {
-                        match NavPoint::new::<StdIoWrapper<std::fs::File>>(&tag) {
+                        match NavPoint::new(&tag) {
                             Ok(n) => {
                                 navp = Some(n);
                             }
@@ -197,7 +1119,7 @@ mod tests {
                         }
                     } else if tag.name == "content" {
                         if let Some(mut n) = navp {
-                            match n.add_content::<StdIoWrapper<std::fs::File>>(&tag) {
+                            match n.add_content(&tag) {
                                 Ok(_) => (),
                                 Err(_) => panic!(),
                             }
@@ -231,6 +1153,451 @@ mod tests {
             panic!();
         }
     }
+
+    type IO = StdIoWrapper<std::fs::File>;
+
+    fn lines(doc: &str) -> Vec<String> {
+        doc.split_inclusive('\n').map(String::from).collect()
+    }
+
+    const NAV_DOC: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"en\">
+<head><title>The
+  Book</title></head>
+<body>
+<nav epub:type=\"toc\" id=\"toc\">
+<h1>Contents</h1>
+<ol>
+<li id=\"p1\"><span>Part   One</span>
+<ol>
+<li><a href=\"ch1.xhtml\">Chapter <em>1</em></a></li>
+<li><a href=\"ch2.xhtml#s1\" xml:lang=\"fr\">Chapitre 2</a></li>
+</ol>
+</li>
+<li hidden=\"\"><a href=\"notes.xhtml\">Notes</a></li>
+</ol>
+</nav>
+<nav epub:type=\"landmarks\" hidden=\"\">
+<ol><li><a epub:type=\"bodymatter\" href=\"ch1.xhtml\">Start</a></li></ol>
+</nav>
+</body>
+</html>
+";
+
+    #[test]
+    fn test_parse_nav() {
+        init();
+        let toc = Toc::parse_nav::<IO>(&lines(NAV_DOC), "book/OEBPS/nav.xhtml", "OEBPS/nav.xhtml")
+            .unwrap();
+        assert_eq!(toc.doc_title, "The Book");
+        let labels: Vec<(usize, &str)> = toc.iter().map(|(d, n)| (d, n.label.as_str())).collect();
+        assert_eq!(
+            labels,
+            [
+                (0, "Part One"),
+                (1, "Chapter 1"),
+                (1, "Chapitre 2"),
+                (0, "Notes")
+            ]
+        );
+        assert_eq!(toc.nav_points.len(), 2);
+        assert_eq!(toc.nav_points[0].children.len(), 2);
+        assert_eq!(toc.depth(), 2);
+        let np: Vec<&NavPoint> = toc.iter().map(|(_, n)| n).collect();
+        assert_eq!(np[0].id, "p1");
+        assert_eq!(np[0].content, "");
+        assert_eq!(np[1].content, "ch1.xhtml");
+        assert_eq!(np[1].play_order, 2);
+        assert_eq!(np[1].lang.as_deref(), Some("en"));
+        assert_eq!(np[2].lang.as_deref(), Some("fr"));
+        assert!(!np[2].hidden);
+        assert!(np[3].hidden);
+        assert_eq!(toc.resolve(&np[2].content).unwrap().path, "OEBPS/ch2.xhtml");
+        assert_eq!(toc.landmarks.len(), 1);
+        assert_eq!(toc.landmarks[0].kind, LandmarkType::Bodymatter);
+        assert_eq!(toc.landmarks[0].title, "Start");
+        assert_eq!(toc.landmarks[0].href.path, "OEBPS/ch1.xhtml");
+    }
+
+    #[test]
+    fn test_parse_ncx() {
+        let doc = "<?xml version=\"1.0\"?>
+<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">
+<head>
+<meta name=\"dtb:uid\" content=\"9781718500457\"/>
+</head>
+<docTitle>
+<text>The Book</text>
+</docTitle>
+<navMap>
+<navPoint id=\"i1\" playOrder=\"1\">
+<navLabel>
+<text>Cover Page</text>
+</navLabel>
+<content src=\"xhtml/cover.xhtml\"/>
+</navPoint>
+</navMap>
+</ncx>
+";
+        let toc = Toc::parse_ncx::<IO>(&lines(doc), "book/OEBPS/toc.ncx", "OEBPS/toc.ncx").unwrap();
+        assert_eq!(toc.doc_title, "The Book");
+        assert_eq!(toc.meta_entries.len(), 1);
+        assert_eq!(toc.nav_points.len(), 1);
+        assert_eq!(toc.nav_points[0].label, "Cover Page");
+    }
+
+    const PAGES_OPF: &str = "<package version=\"3.0\" unique-identifier=\"uid\">
+<manifest>
+<item id=\"front\" href=\"xhtml/front.xhtml\" media-type=\"application/xhtml+xml\"/>
+<item id=\"ch1\" href=\"xhtml/ch1.xhtml\" media-type=\"application/xhtml+xml\"/>
+<item id=\"ch2\" href=\"xhtml/ch2.xhtml\" media-type=\"application/xhtml+xml\"/>
+</manifest>
+<spine>
+<itemref idref=\"front\"/>
+<itemref idref=\"ch1\"/>
+<itemref idref=\"ch2\"/>
+</spine>
+</package>
+";
+
+    fn package() -> Package {
+        let rootfile = crate::container::Rootfile {
+            full_path: String::from("book/OEBPS/content.opf"),
+            media_type: String::from("application/oebps-package+xml"),
+            root_dir: String::from("book"),
+            path: String::from("OEBPS/content.opf"),
+        };
+        Package::parse::<IO>(&lines(PAGES_OPF), &rootfile, crate::ParseMode::Strict).unwrap()
+    }
+
+    fn position(index: usize, fragment: Option<&str>) -> SpinePosition {
+        SpinePosition {
+            index,
+            fragment: fragment.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_ncx_page_list() {
+        let doc = "<ncx><navMap></navMap>
+<pageList>
+<navLabel><text>Pages</text></navLabel>
+<pageTarget id=\"pi\" type=\"front\" value=\"1\" playOrder=\"1\"><navLabel><text>i</text></navLabel><content src=\"xhtml/front.xhtml\"/></pageTarget>
+<pageTarget id=\"p1\" type=\"normal\" value=\"1\" playOrder=\"2\"><navLabel><text>1</text></navLabel><content src=\"xhtml/ch1.xhtml\"/></pageTarget>
+<pageTarget id=\"p2\" type=\"normal\" value=\"2\" playOrder=\"3\"><navLabel><text>2</text></navLabel><content src=\"xhtml/ch1.xhtml#page2\"/></pageTarget>
+<pageTarget id=\"p3\" type=\"special\" playOrder=\"4\"><navLabel><text>Plate</text></navLabel><content src=\"xhtml/missing.xhtml\"/></pageTarget>
+</pageList>
+</ncx>
+";
+        let mut toc =
+            Toc::parse_ncx::<IO>(&lines(doc), "book/OEBPS/toc.ncx", "OEBPS/toc.ncx").unwrap();
+        assert_eq!(toc.page_list.len(), 4);
+        assert_eq!(toc.page_list[0].page_type, PageType::Front);
+        assert_eq!(toc.page_list[2].value, Some(2));
+        assert_eq!(toc.page_list[3].page_type, PageType::Special);
+        assert_eq!(toc.page_list[3].value, None);
+        toc.resolve_positions(&package());
+        assert_eq!(toc.page_list[0].position, Some(position(0, None)));
+        assert_eq!(toc.page_list[2].position, Some(position(1, Some("page2"))));
+        assert_eq!(toc.page_list[3].position, None);
+        assert_eq!(toc.page("2").unwrap().id, "p2");
+        assert!(toc.page("99").is_none());
+        assert_eq!(toc.current_page(&position(1, None)).unwrap().id, "p1");
+        assert_eq!(
+            toc.current_page(&position(1, Some("page2"))).unwrap().id,
+            "p2"
+        );
+        assert_eq!(toc.current_page(&position(2, None)).unwrap().id, "p2");
+    }
+
+    #[test]
+    fn test_nav_page_list() {
+        let doc = "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">
+<body>
+<nav epub:type=\"page-list\" hidden=\"\">
+<ol>
+<li><a href=\"../xhtml/front.xhtml\">i</a></li>
+<li><a href=\"../xhtml/ch1.xhtml#p1\">1</a></li>
+</ol>
+</nav>
+</body>
+</html>
+";
+        let mut toc = Toc::parse_nav::<IO>(
+            &lines(doc),
+            "book/OEBPS/nav/nav.xhtml",
+            "OEBPS/nav/nav.xhtml",
+        )
+        .unwrap();
+        assert!(toc.nav_points.is_empty());
+        assert_eq!(toc.page_list.len(), 2);
+        assert_eq!(toc.page_list[0].value, None);
+        assert_eq!(toc.page_list[1].value, Some(1));
+        toc.resolve_positions(&package());
+        assert_eq!(toc.page_list[1].position, Some(position(1, Some("p1"))));
+    }
+
+    #[test]
+    fn test_entry_positions() {
+        let doc = "<ncx><navMap>
+<navPoint id=\"front\" playOrder=\"1\"><navLabel><text>Front</text></navLabel><content src=\"xhtml/front.xhtml\"/></navPoint>
+<navPoint id=\"ch1\" playOrder=\"2\"><navLabel><text>Chapter 1</text></navLabel><content src=\"xhtml/ch1.xhtml\"/>
+<navPoint id=\"ch1-1\" playOrder=\"3\"><navLabel><text>Section 1</text></navLabel><content src=\"xhtml/ch1.xhtml\"/></navPoint>
+<navPoint id=\"ch1-2\" playOrder=\"4\"><navLabel><text>Section 2</text></navLabel><content src=\"xhtml/ch1.xhtml#sec2\"/></navPoint>
+</navPoint>
+<navPoint id=\"gone\" playOrder=\"5\"><navLabel><text>Gone</text></navLabel><content src=\"xhtml/missing.xhtml\"/></navPoint>
+</navMap></ncx>
+";
+        let mut toc =
+            Toc::parse_ncx::<IO>(&lines(doc), "book/OEBPS/toc.ncx", "OEBPS/toc.ncx").unwrap();
+        toc.resolve_positions(&package());
+        let ch1 = toc.entry("ch1").unwrap();
+        assert_eq!(ch1.position, Some(position(1, None)));
+        assert_eq!(
+            toc.entry("ch1-2").unwrap().position,
+            Some(position(1, Some("sec2")))
+        );
+        assert_eq!(toc.entry("gone").unwrap().position, None);
+        assert!(toc.entry("none").is_none());
+        assert_eq!(toc.current_entry(&position(0, None)).unwrap().id, "front");
+        assert_eq!(toc.current_entry(&position(1, None)).unwrap().id, "ch1-1");
+        assert_eq!(
+            toc.current_entry(&position(1, Some("sec2"))).unwrap().id,
+            "ch1-2"
+        );
+        assert_eq!(
+            toc.current_entry(&position(1, Some("other"))).unwrap().id,
+            "ch1-1"
+        );
+        assert_eq!(toc.current_entry(&position(2, None)).unwrap().id, "ch1-2");
+    }
+
+    #[test]
+    fn test_ncx_errors() {
+        for (doc, element, kind) in &[
+            (
+                "<ncx><navMap><navPoint id=\"a\"></navPoint></navMap></ncx>",
+                "navPoint",
+                ParseErrorKind::MissingAttribute("playOrder"),
+            ),
+            (
+                "<ncx><navMap><navPoint id=\"a\" playOrder=\"x\"></navPoint></navMap></ncx>",
+                "navPoint",
+                ParseErrorKind::InvalidAttribute("playOrder"),
+            ),
+            (
+                "<ncx><navList><navTarget playOrder=\"1\"></navTarget></navList></ncx>",
+                "navTarget",
+                ParseErrorKind::MissingAttribute("id"),
+            ),
+            (
+                "<ncx><navMap><navPoint id=\"a\" playOrder=\"1\"><content/></navPoint></navMap></ncx>",
+                "content",
+                ParseErrorKind::MissingAttribute("src"),
+            ),
+        ] {
+            match Toc::parse_ncx::<IO>(&lines(doc), "book/OEBPS/toc.ncx", "OEBPS/toc.ncx") {
+                Err(EPubError::Parse(e)) => {
+                    assert_eq!(e.file, "book/OEBPS/toc.ncx");
+                    assert_eq!(e.element, *element);
+                    assert_eq!(e.kind, *kind);
+                }
+                _ => panic!("{}", doc),
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry_at_offset() {
+        let doc = "<ncx><navMap>
+<navPoint id=\"front\" playOrder=\"1\"><navLabel><text>Front</text></navLabel><content src=\"xhtml/front.xhtml#top\"/></navPoint>
+<navPoint id=\"ch1\" playOrder=\"2\"><navLabel><text>Chapter 1</text></navLabel><content src=\"xhtml/ch1.xhtml\"/>
+<navPoint id=\"ch1-2\" playOrder=\"3\"><navLabel><text>Section 2</text></navLabel><content src=\"xhtml/ch1.xhtml#sec2\"/></navPoint>
+<navPoint id=\"ch1-3\" playOrder=\"4\"><navLabel><text>Section 3</text></navLabel><content src=\"xhtml/ch1.xhtml#sec3\"/></navPoint>
+<navPoint id=\"ch1-x\" playOrder=\"5\"><navLabel><text>Unknown</text></navLabel><content src=\"xhtml/ch1.xhtml#nowhere\"/></navPoint>
+</navPoint>
+</navMap></ncx>
+";
+        let mut toc =
+            Toc::parse_ncx::<IO>(&lines(doc), "book/OEBPS/toc.ncx", "OEBPS/toc.ncx").unwrap();
+        toc.resolve_positions(&package());
+        let ch1 = "<html>
+<body>
+<h1 id=\"top\">Chapter 1</h1>
+<p>Text</p>
+<h2 id=\"sec2\">Section 2</h2>
+<p>Text</p>
+<h2
+ id=\"sec3\">Section 3</h2>
+</body>
+</html>
+";
+        let anchors = anchor_offsets(&lines(ch1));
+        let ids: Vec<&str> = anchors.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, ["top", "sec2", "sec3"]);
+        // offsets are of the line the start tag ends on
+        assert_eq!(anchors[1].offset, ch1.find("<h2").unwrap());
+        assert_eq!(anchors[2].offset, ch1.find(" id=\"sec3\"").unwrap());
+        let at = |index, offset| {
+            toc.entry_at_offset(index, offset, &anchors)
+                .unwrap()
+                .id
+                .as_str()
+        };
+        // the anchors are those of ch1, so the front entry can't be placed
+        assert!(toc.entry_at_offset(0, 0, &anchors).is_none());
+        assert_eq!(at(1, 0), "ch1");
+        assert_eq!(at(1, anchors[1].offset - 1), "ch1");
+        assert_eq!(at(1, anchors[1].offset), "ch1-2");
+        assert_eq!(at(1, anchors[2].offset + 5), "ch1-3");
+        // the last entry of an earlier spine item, in reading order
+        assert_eq!(at(2, 0), "ch1-x");
+    }
+
+    #[test]
+    fn test_document_title() {
+        let doc = "<html xmlns=\"http://www.w3.org/1999/xhtml\">
+<head><title>The Book</title></head>
+<body>
+<p>Epigraph</p>
+<section><h2 class=\"chapter\">Chapter <em>One</em>
+</h2><h1>Later</h1></section>
+</body>
+</html>
+";
+        assert_eq!(document_title(&lines(doc)).as_deref(), Some("Chapter One"));
+        let doc =
+            "<html><head><title>  Just a\n title </title></head><body><p>Text</p></body></html>";
+        assert_eq!(document_title(&lines(doc)).as_deref(), Some("Just a title"));
+        let doc = "<html><head><title></title></head><body><h3> </h3><p>Text</p></body></html>";
+        assert_eq!(document_title(&lines(doc)), None);
+    }
+
+    #[test]
+    fn test_synthesized_entries_keep_positions() {
+        let mut toc = Toc::empty("OEBPS/content.opf");
+        assert!(!toc.has_entries());
+        toc.nav_points.push(NavPoint {
+            id: String::from("ch1"),
+            play_order: 1,
+            label: String::from("ch1"),
+            content: String::from("xhtml/ch1.xhtml"),
+            hidden: false,
+            lang: None,
+            epub_type: None,
+            children: Vec::new(),
+            position: Some(position(1, None)),
+            synthesized: true,
+        });
+        toc.resolve_positions(&package());
+        assert!(toc.has_entries());
+        assert_eq!(toc.entry("ch1").unwrap().position, Some(position(1, None)));
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let mut disk = StdIoWrapper::from(std::io::Cursor::new(vec![0u8; 1024 * 1024]));
+        fatfs::format_volume(&mut disk, fatfs::FormatVolumeOptions::new()).unwrap();
+        let mut fs = FileSystem::new(disk, fatfs::FsOptions::new()).unwrap();
+        if io::create_dirs("book/OEBPS/xhtml", &mut fs).is_err() {
+            panic!();
+        }
+        for (name, doc) in &[
+            (
+                "front",
+                "<html><head><title>Front Matter</title></head></html>",
+            ),
+            ("ch1", "<html><body><h1>Chapter One</h1></body></html>"),
+        ] {
+            let path = alloc::format!("book/OEBPS/xhtml/{}.xhtml", name);
+            let mut file = fs.root_dir().create_file(&path).unwrap();
+            fatfs::Write::write_all(&mut file, doc.as_bytes()).unwrap();
+        }
+        // the nav is in another directory than the package document
+        let mut toc = Toc::empty("OEBPS/nav/nav.xhtml");
+        let pkg = package();
+        toc.synthesize(&pkg, &mut fs);
+        let labels: Vec<&str> = toc.nav_points.iter().map(|np| np.label.as_str()).collect();
+        assert_eq!(labels, ["Front Matter", "Chapter One", "ch2"]);
+        let ch1 = toc.entry("ch1").unwrap();
+        assert_eq!(ch1.content, "../xhtml/ch1.xhtml");
+        assert_eq!(ch1.position, Some(position(1, None)));
+        let href = toc.resolve(&ch1.content).unwrap();
+        assert_eq!(href.path, "OEBPS/xhtml/ch1.xhtml");
+        assert_eq!(pkg.locate(&href), Some(position(1, None)));
+        toc.resolve_positions(&pkg);
+        assert_eq!(toc.entry("ch2").unwrap().position, Some(position(2, None)));
+    }
+
+    #[test]
+    fn test_ncx_head_and_lists() {
+        let doc = "<?xml version=\"1.0\"?>
+<ncx:ncx xmlns:ncx=\"http://www.daisy.org/z3986/2005/ncx/\" xmlns:svg=\"http://www.w3.org/2000/svg\" version=\"2005-1\">
+<ncx:head>
+<ncx:meta name=\"dtb:uid\" content=\" urn:isbn:123 \"/>
+<ncx:meta name=\"dtb:depth\" content=\"2\"/>
+<ncx:meta name=\"dtb:totalPageCount\" content=\"320\"/>
+<ncx:meta name=\"dtb:maxPageNumber\" content=\"x\"/>
+</ncx:head>
+<ncx:docTitle><ncx:text>The Book</ncx:text></ncx:docTitle>
+<ncx:docAuthor><ncx:text>First Author</ncx:text></ncx:docAuthor>
+<ncx:docAuthor><ncx:text>Second Author</ncx:text></ncx:docAuthor>
+<ncx:navMap>
+<ncx:navPoint id=\"c1\" playOrder=\"1\"><ncx:navLabel><ncx:text>Chapter 1</ncx:text><svg:text>ignored</svg:text></ncx:navLabel><ncx:content src=\"c1.xhtml\"/></ncx:navPoint>
+</ncx:navMap>
+<ncx:navList class=\"lot\" id=\"tables\">
+<ncx:navLabel><ncx:text>List of Tables</ncx:text></ncx:navLabel>
+<ncx:navTarget id=\"t1\" playOrder=\"2\"><ncx:navLabel><ncx:text>Table 1</ncx:text></ncx:navLabel><ncx:content src=\"c1.xhtml#t1\"/></ncx:navTarget>
+<ncx:navTarget id=\"t2\" playOrder=\"3\"><ncx:navLabel><ncx:text>Table 2</ncx:text></ncx:navLabel><ncx:content src=\"c1.xhtml#t2\"/></ncx:navTarget>
+</ncx:navList>
+</ncx:ncx>
+";
+        let toc = Toc::parse_ncx::<IO>(&lines(doc), "toc.ncx", "toc.ncx").unwrap();
+        assert_eq!(toc.dtb.uid.as_deref(), Some("urn:isbn:123"));
+        assert_eq!(toc.dtb.depth, Some(2));
+        assert_eq!(toc.dtb.total_page_count, Some(320));
+        assert_eq!(toc.dtb.max_page_number, None);
+        assert_eq!(toc.meta_entries.len(), 4);
+        assert_eq!(toc.doc_title, "The Book");
+        assert_eq!(toc.doc_authors, ["First Author", "Second Author"]);
+        assert_eq!(toc.nav_points.len(), 1);
+        assert_eq!(toc.nav_points[0].label, "Chapter 1");
+        assert_eq!(toc.nav_lists.len(), 1);
+        let nl = &toc.nav_lists[0];
+        assert_eq!(nl.class.as_deref(), Some("lot"));
+        assert_eq!(nl.id.as_deref(), Some("tables"));
+        assert_eq!(nl.label, "List of Tables");
+        assert_eq!(nl.targets.len(), 2);
+        assert_eq!(nl.targets[1].label, "Table 2");
+        assert_eq!(nl.targets[1].content, "c1.xhtml#t2");
+    }
+
+    #[test]
+    fn test_parse_ncx_nested() {
+        let doc = "<ncx><navMap>
+<navPoint id=\"p1\" playOrder=\"1\"><navLabel><text>Part 1</text></navLabel><content src=\"p1.xhtml\"/>
+<navPoint id=\"c1\" playOrder=\"2\"><navLabel><text>Chapter 1</text></navLabel><content src=\"c1.xhtml\"/>
+<navPoint id=\"s1\" playOrder=\"3\"><navLabel><text>Section 1</text></navLabel><content src=\"c1.xhtml#s1\"/></navPoint>
+</navPoint>
+<navPoint id=\"c2\" playOrder=\"4\"><navLabel><text>Chapter 2</text></navLabel><content src=\"c2.xhtml\"/></navPoint>
+</navPoint>
+<navPoint id=\"p2\" playOrder=\"5\"><navLabel><text>Part 2</text></navLabel><content src=\"p2.xhtml\"/></navPoint>
+</navMap></ncx>
+";
+        let toc = Toc::parse_ncx::<IO>(&lines(doc), "toc.ncx", "toc.ncx").unwrap();
+        assert_eq!(toc.nav_points.len(), 2);
+        let p1 = &toc.nav_points[0];
+        assert_eq!(p1.label, "Part 1");
+        assert_eq!(p1.content, "p1.xhtml");
+        assert_eq!(p1.children.len(), 2);
+        assert_eq!(p1.children[0].children[0].content, "c1.xhtml#s1");
+        let entries: Vec<(usize, &str)> = toc.iter().map(|(d, n)| (d, n.id.as_str())).collect();
+        assert_eq!(
+            entries,
+            [(0, "p1"), (1, "c1"), (2, "s1"), (1, "c2"), (0, "p2")]
+        );
+        assert_eq!(toc.depth(), 3);
+    }
 }
 
 /*
@@ -1,14 +1,28 @@
 //! the EPub Package Document
 //! https://www.w3.org/publishing/epub32/epub-packages.html#sec-package-doc
 
+use crate::container::Rootfile;
+use crate::date::Date;
 use crate::io;
-use crate::io::BufReader;
-use crate::EPubError;
-use alloc::{string::String, vec::Vec};
-use fatfs::{FileSystem, OemCpConverter, ReadWriteSeek, Seek, SeekFrom, TimeProvider};
+use crate::io::{Href, HrefError};
+use crate::{EPubError, ParseError, ParseErrorKind, ParseMode};
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use fatfs::{FileSystem, OemCpConverter, ReadWriteSeek, TimeProvider};
 use log::{info, trace, warn};
 use xml::{Event, Parser, StartTag};
 
+/// media type of the EPUB 2 NCX
+pub const NCX_MEDIA_TYPE: &str = "application/x-dtbncx+xml";
+
+/// the document a table of contents is read from
+#[derive(Debug, Clone, Copy)]
+pub enum TocSource<'a> {
+    /// the EPUB 3 navigation document
+    Nav(&'a Item),
+    /// the EPUB 2 NCX
+    Ncx(&'a Item),
+}
+
 /// Package from EPub file
 #[derive(Debug)]
 pub struct Package {
@@ -27,96 +41,183 @@ pub struct Package {
     pub manifest: Manifest,
     /// `spine` section
     pub spine: Spine,
+    /// the EPUB 2 `guide` section
+    pub guide: Vec<Landmark>,
     /// the base directory
     ///
     /// This is where the bulk of the books' files reside
     pub base_dir: String,
+    /// the directory the container was expanded into
+    pub root_dir: String,
+    /// path of the package document relative to the container root
+    pub path: String,
+    /// malformed elements skipped while reading in `ParseMode::Lenient`
+    pub warnings: Vec<ParseError>,
 }
 
 impl Package {
-    /// read the package data from the file
+    /// read the package data from the rootfile
     pub fn read<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
-        opf_file_name: &str,
+        rootfile: &Rootfile,
+        fs: &mut FileSystem<IO, TP, OCC>,
+    ) -> Result<Package, EPubError<IO>> {
+        Package::read_with(rootfile, ParseMode::Strict, fs)
+    }
+
+    /// read the package data from the rootfile, handling malformed elements
+    /// according to `mode`
+    pub fn read_with<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+        rootfile: &Rootfile,
+        mode: ParseMode,
         fs: &mut FileSystem<IO, TP, OCC>,
     ) -> Result<Package, EPubError<IO>> {
+        Package::read_with_blocks(rootfile, mode, fs, &mut io::heap_blocks())
+    }
+
+    /// read the package data like `read_with`, using the caller's
+    /// `BufReader` blocks
+    pub fn read_with_blocks<
+        IO: ReadWriteSeek,
+        TP: TimeProvider,
+        OCC: OemCpConverter,
+        const N: usize,
+    >(
+        rootfile: &Rootfile,
+        mode: ParseMode,
+        fs: &mut FileSystem<IO, TP, OCC>,
+        blocks: &mut [[u8; N]; 2],
+    ) -> Result<Package, EPubError<IO>> {
+        let lines = io::read_file_lines(&rootfile.full_path, fs, blocks)?;
+        Package::parse(&lines, rootfile, mode)
+    }
+
+    /// parse the lines of the package document
+    pub(crate) fn parse<IO: ReadWriteSeek>(
+        lines: &[String],
+        rootfile: &Rootfile,
+        mode: ParseMode,
+    ) -> Result<Package, EPubError<IO>> {
+        let opf_file_name = rootfile.full_path.as_str();
         // get the leading directories from the file name
-        let base_name = io::basename_and_ext(opf_file_name);
-        let mut split = opf_file_name.split(&base_name.0);
-        let base_dir = String::from(split.next().unwrap_or(""));
-        // open the file
-        let root_dir = fs.root_dir();
-        let mut opf_file = root_dir.open_file(opf_file_name)?;
-        info!("Opened '{}' package", opf_file_name);
-        let _file_len = opf_file.seek(SeekFrom::End(0))?;
-        opf_file.seek(SeekFrom::Start(0))?;
-        let mut rdr = BufReader::new(opf_file)?;
+        let base_dir = String::from(io::dirname(opf_file_name));
         let mut p = Parser::new();
-        let lines = rdr.read_lines()?;
         let mut stack: Vec<Event> = Vec::new();
         let mut chars = String::new();
         let mut metadata = Metadata::new();
-        let mut manifest = Manifest::new();
+        let mut manifest = Manifest::with_path(&rootfile.path);
         let mut spine = Spine::new();
+        let mut warnings: Vec<ParseError> = Vec::new();
         let mut in_metadata = false;
         let mut in_manifest = false;
         let mut in_spine = false;
+        let mut in_guide = false;
+        let mut guide: Vec<Landmark> = Vec::new();
+        // the role and metadata of the outermost `collection` being read,
+        // and how many collections deep we are
+        let mut collection: Option<(Option<String>, Metadata)> = None;
+        let mut collection_depth = 0;
+        let mut in_collection_metadata = false;
         // the attributes on package
         let mut package_uid: Option<String> = None;
         let mut version: Option<String> = None;
         let mut xml_lang: Option<String> = None;
+        // report a malformed element, or keep going in lenient mode
+        let mut recover = |res: Result<(), ParseError>| -> Result<(), ParseError> {
+            if let Err(e) = res {
+                let e = e.in_file(opf_file_name);
+                if mode == ParseMode::Strict {
+                    return Err(e);
+                }
+                warn!("Skipping malformed element: {:?}", e);
+                warnings.push(e);
+            }
+            Ok(())
+        };
         for ln in lines {
-            p.feed_str(&ln);
+            p.feed_str(ln);
             for event in &mut p {
                 match event {
                     Ok(e) => match e {
                         Event::PI(s) => info!("PI({})", s),
                         Event::ElementStart(tag) => {
                             trace!("Start({})", tag.name);
-                            if tag.name == "metadata" {
+                            if tag.name == "collection" {
+                                if collection_depth == 0 {
+                                    let role =
+                                        tag.attributes.get(&(String::from("role"), None)).cloned();
+                                    collection = Some((role, Metadata::new()));
+                                }
+                                collection_depth += 1;
+                            } else if collection_depth > 0 {
+                                if tag.name == "metadata" && collection_depth == 1 {
+                                    in_collection_metadata = true;
+                                }
+                            } else if tag.name == "metadata" {
                                 in_metadata = true;
                             } else if tag.name == "manifest" {
                                 in_manifest = true;
                             } else if tag.name == "spine" {
                                 in_spine = true;
-                                spine.add_tag(&tag);
+                                recover(spine.add_tag(&tag))?;
+                            } else if tag.name == "guide" {
+                                in_guide = true;
                             }
                             stack.push(Event::ElementStart(tag));
                             chars = String::new();
                         }
                         Event::ElementEnd(tag) => {
                             trace!("End({})", tag.name);
-                            if let Some(last) = stack.pop() {
-                                match last {
-                                    Event::ElementStart(start_tag) => {
-                                        if tag.name == "metadata" {
-                                            in_metadata = false;
-                                        } else if tag.name == "package" {
-                                            let (a1, a2, a3) =
-                                                Package::collect_attributes(&start_tag);
-                                            package_uid = Some(a1);
-                                            version = Some(a2);
-                                            xml_lang = a3;
-                                        } else if tag.name == "manifest" {
-                                            in_manifest = false;
-                                        } else if tag.name == "spine" {
-                                            in_spine = false;
+                            if let Some(Event::ElementStart(start_tag)) = stack.pop() {
+                                if start_tag.name != tag.name {
+                                    recover(Err(ParseError {
+                                        file: String::new(),
+                                        element: start_tag.name.clone(),
+                                        kind: ParseErrorKind::MismatchedEnd(tag.name.clone()),
+                                    }))?;
+                                }
+                                if collection_depth > 0 {
+                                    if tag.name == "collection" {
+                                        collection_depth -= 1;
+                                        if collection_depth == 0 {
+                                            if let Some((role, md)) = collection.take() {
+                                                metadata.add_collection(role.as_deref(), md);
+                                            }
                                         }
-                                        if in_metadata {
-                                            metadata.add_tag(&start_tag, &chars);
-                                        } else if in_manifest {
-                                            manifest.add_tag(&start_tag);
-                                        } else if in_spine {
-                                            spine.add_tag(&start_tag);
-                                        } else {
-                                            trace!(
-                                                "completed '{}' with chars '{}'",
-                                                tag.name,
-                                                chars
-                                            );
+                                    } else if tag.name == "metadata" {
+                                        in_collection_metadata = false;
+                                    } else if in_collection_metadata {
+                                        if let Some((_, md)) = &mut collection {
+                                            recover(md.add_tag(&start_tag, &chars))?;
                                         }
-                                        assert!(start_tag.name == tag.name);
                                     }
-                                    _ => (),
+                                    continue;
+                                }
+                                if tag.name == "metadata" {
+                                    in_metadata = false;
+                                } else if tag.name == "package" {
+                                    let (a1, a2, a3) = Package::collect_attributes(&start_tag);
+                                    package_uid = a1;
+                                    version = a2;
+                                    xml_lang = a3;
+                                } else if tag.name == "manifest" {
+                                    in_manifest = false;
+                                } else if tag.name == "spine" {
+                                    in_spine = false;
+                                } else if tag.name == "guide" {
+                                    in_guide = false;
+                                }
+                                if in_metadata {
+                                    recover(metadata.add_tag(&start_tag, &chars))?;
+                                } else if in_manifest {
+                                    recover(manifest.add_tag(&start_tag))?;
+                                } else if in_spine {
+                                    recover(spine.add_tag(&start_tag))?;
+                                } else if in_guide && tag.name == "reference" {
+                                    let reference =
+                                        Landmark::from_reference(&start_tag, &rootfile.path);
+                                    recover(reference.map(|l| guide.extend(l)))?;
+                                } else {
+                                    trace!("completed '{}' with chars '{}'", tag.name, chars);
                                 }
                             }
                         }
@@ -136,46 +237,254 @@ impl Package {
             }
         }
         info!("Finished parsing '{}' package", opf_file_name);
-        if let Some(uid) = package_uid {
-            if let Some(ver) = version {
-                Ok(Package {
-                    unique_identifer: uid,
-                    version: ver,
-                    xml_lang,
-                    //prefix: None,
-                    //id: None,
-                    //dir: None,
-                    metadata,
-                    manifest,
-                    spine,
-                    base_dir,
-                })
-            } else {
-                panic!();
-            }
+        metadata.resolve_refinements();
+        if package_uid.is_none() {
+            recover(Err(ParseError::missing_attribute(
+                "package",
+                "unique-identifier",
+            )))?;
+        }
+        if version.is_none() {
+            recover(Err(ParseError::missing_attribute("package", "version")))?;
+        }
+        Ok(Package {
+            unique_identifer: package_uid.unwrap_or_default(),
+            version: version.unwrap_or_default(),
+            xml_lang,
+            //prefix: None,
+            //id: None,
+            //dir: None,
+            metadata,
+            manifest,
+            spine,
+            guide,
+            base_dir,
+            root_dir: rootfile.root_dir.clone(),
+            path: rootfile.path.clone(),
+            warnings,
+        })
+    }
+
+    /// the `dc:identifier` named by the `unique-identifier` attribute
+    pub fn identifier(&self) -> Option<&DcElement> {
+        self.metadata.identifier(&self.unique_identifer)
+    }
+
+    /// the manifest item of the NCX, named by the spine `toc` attribute or
+    /// else found by media type
+    pub fn ncx_item(&self) -> Option<&Item> {
+        // the spine `toc` may name an id that isn't in the manifest
+        self.spine
+            .toc
+            .as_ref()
+            .and_then(|id| self.manifest.item(id))
+            .or_else(|| {
+                self.manifest
+                    .items()
+                    .iter()
+                    .find(|i| i.media_type == NCX_MEDIA_TYPE)
+            })
+    }
+
+    /// where to read the table of contents from, the EPUB 3 navigation
+    /// document if there is one, or else the NCX
+    pub fn toc_source(&self) -> Option<TocSource<'_>> {
+        if let Some(item) = self.manifest.nav_item() {
+            Some(TocSource::Nav(item))
         } else {
-            panic!();
+            self.ncx_item().map(TocSource::Ncx)
         }
     }
 
-    fn collect_attributes(start_tag: &StartTag) -> (String, String, Option<String>) {
-        let mut uidstr = String::new();
-        let mut verstr = String::new();
-        let mut langstr: Option<String> = None;
-        if let Some(uid) = start_tag
-            .attributes
-            .get(&(String::from("unique-identifier"), None))
+    /// resolve an href found in the package document
+    pub fn resolve(&self, href: &str) -> Result<Href, HrefError> {
+        io::resolve_href(&self.path, href)
+    }
+
+    /// the spine position of a resolved href, if it is a spine item
+    pub fn locate(&self, href: &Href) -> Option<SpinePosition> {
+        let item = self.item_at(href)?;
+        let index = self
+            .spine
+            .itemrefs
+            .iter()
+            .position(|r| r.idref == item.id)?;
+        Some(SpinePosition {
+            index,
+            fragment: href.fragment.clone(),
+        })
+    }
+
+    /// the manifest item for a resolved href
+    pub fn item_at(&self, href: &Href) -> Option<&Item> {
+        self.manifest.item_at_path(&href.path)
+    }
+
+    /// the manifest item of the spine item at `index`, and the path of its
+    /// file on the filesystem
+    pub fn spine_item(&self, index: usize) -> Option<(&Item, String)> {
+        let itemref = self.spine.itemrefs.get(index)?;
+        let item = self.manifest.item(&itemref.idref)?;
+        let href = self.resolve(&item.href).ok()?;
+        Some((item, self.disk_path(&href)))
+    }
+
+    /// the cover image named in the package document
+    ///
+    /// the manifest item with the `cover-image` property, or else the item
+    /// named by an EPUB 2 `<meta name="cover">`
+    pub fn cover_item(&self) -> Option<&Item> {
+        self.manifest.cover_image_item().or_else(|| {
+            self.metadata
+                .meta_tags()
+                .iter()
+                .filter(|m| m.name == "cover")
+                .find_map(|m| self.manifest.item(m.content.trim()))
+                .filter(|i| i.is_image())
+        })
+    }
+
+    /// find the book's cover image
+    ///
+    /// tries `cover_item`, then the cover landmark, then the first linear
+    /// spine item. A landmark or spine item that is an XHTML page gives the
+    /// first image on that page.
+    pub fn cover<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+        &self,
+        landmarks: &[Landmark],
+        fs: &mut FileSystem<IO, TP, OCC>,
+    ) -> Option<Cover> {
+        if let Some(cover) = self.cover_item().and_then(|i| self.cover_from_item(i)) {
+            return Some(cover);
+        }
+        let mut pages: Vec<Href> = landmarks
+            .iter()
+            .filter(|l| l.kind == LandmarkType::Cover)
+            .map(|l| l.href.clone())
+            .collect();
+        if let Some(first) = self.spine.first() {
+            let first = &self.spine.itemrefs[first];
+            if let Some(href) = self
+                .manifest
+                .item(&first.idref)
+                .and_then(|i| self.resolve(&i.href).ok())
+            {
+                pages.push(href);
+            }
+        }
+        let mut blocks = io::heap_blocks();
+        for page in pages {
+            match self.item_at(&page) {
+                Some(item) if item.is_image() => match self.cover_from_item(item) {
+                    Some(cover) => return Some(cover),
+                    None => continue,
+                },
+                Some(_) => (),
+                None => continue,
+            }
+            let file_name = self.disk_path(&page);
+            let lines = match io::read_file_lines(&file_name, fs, &mut blocks) {
+                Ok(lines) => lines,
+                Err(_) => {
+                    warn!("Unable to read '{}'", file_name);
+                    continue;
+                }
+            };
+            if let Some(cover) = self.image_on_page(&page, &lines) {
+                return Some(cover);
+            }
+        }
+        None
+    }
+
+    /// the first image on the XHTML page at `page`, with its lines
+    pub fn image_on_page(&self, page: &Href, lines: &[String]) -> Option<Cover> {
+        let src = first_image(lines)?;
+        let href = match io::resolve_href(&page.path, &src) {
+            Ok(href) => href,
+            Err(e) => {
+                warn!("Image '{}' has a bad href: {:?}", src, e);
+                return None;
+            }
+        };
+        match self.item_at(&href) {
+            Some(item) if item.is_image() => self.cover_from_item(item),
+            _ => {
+                warn!("Image '{}' is not an image in the manifest", href.path);
+                None
+            }
+        }
+    }
+
+    fn cover_from_item(&self, item: &Item) -> Option<Cover> {
+        let href = self.resolve(&item.href).ok()?;
+        Some(Cover {
+            path: self.disk_path(&href),
+            media_type: item.media_type.clone(),
+            href,
+        })
+    }
+
+    /// where to open a book that has not been read yet
+    ///
+    /// the `bodymatter` landmark if there is one, or else the first linear
+    /// spine item that isn't the cover, title page, copyright page or a table
+    /// of contents. `landmarks` should be the navigation document landmarks
+    /// if there are any, or else `guide`.
+    pub fn start_position(&self, landmarks: &[Landmark]) -> Option<SpinePosition> {
+        if let Some(pos) = landmarks
+            .iter()
+            .filter(|l| l.kind == LandmarkType::Bodymatter)
+            .find_map(|l| self.locate(&l.href))
         {
-            uidstr += uid;
+            return Some(pos);
         }
-        if let Some(ver) = start_tag.attributes.get(&(String::from("version"), None)) {
-            verstr += ver;
+        let skipped: Vec<usize> = landmarks
+            .iter()
+            .filter(|l| l.kind.is_preliminary())
+            .filter_map(|l| self.locate(&l.href))
+            .map(|pos| pos.index)
+            .collect();
+        let nav = self.manifest.nav_item().map(|i| i.id.as_str());
+        let mut idx = self.spine.first();
+        while let Some(i) = idx {
+            let itemref = &self.spine.itemrefs[i];
+            if !skipped.contains(&i) && Some(itemref.idref.as_str()) != nav {
+                return Some(SpinePosition {
+                    index: i,
+                    fragment: None,
+                });
+            }
+            idx = self.spine.next(i);
         }
+        self.spine.first().map(|index| SpinePosition {
+            index,
+            fragment: None,
+        })
+    }
+
+    /// the path on disk of a resolved href
+    pub fn disk_path(&self, href: &Href) -> String {
+        io::join_path(&self.root_dir, &href.path)
+    }
+
+    fn collect_attributes(
+        start_tag: &StartTag,
+    ) -> (Option<String>, Option<String>, Option<String>) {
+        let uidstr = start_tag
+            .attributes
+            .get(&(String::from("unique-identifier"), None))
+            .cloned();
+        let verstr = start_tag
+            .attributes
+            .get(&(String::from("version"), None))
+            .cloned();
+        let mut langstr: Option<String> = None;
         // optional
-        if let Some(lang) = start_tag.attributes.get(&(
-            String::from("lang"),
-            Some(String::from("http://www.w3.org/XML/1998/namespace")),
-        )) {
+        if let Some(lang) = start_tag
+            .attributes
+            .get(&(String::from("lang"), Some(String::from(XML_NS))))
+        {
             langstr = Some(String::from(lang));
         }
         (uidstr, verstr, langstr)
@@ -183,182 +492,923 @@ impl Package {
 }
 
 /// Meta tag entry from opf file
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Meta {
     /// name - property name
     pub name: String,
     pub content: String,
+    /// attribute `id`
+    pub id: Option<String>,
+    /// attribute `refines`, the id of the element this meta refines
+    pub refines: Option<String>,
+    /// attribute `scheme`
+    pub scheme: Option<String>,
+    /// attribute `xml:lang`
+    pub lang: Option<String>,
+    /// metas refining this one
+    pub refinements: Vec<Meta>,
 }
 
 impl Meta {
     /// create a new meta entry from xml tag 'meta'
-    pub fn new(tag: &StartTag, chars: &str) -> Meta {
+    pub fn new(tag: &StartTag, chars: &str) -> Result<Meta, ParseError> {
         // opf3 version
-        if let Some(prop) = tag.attributes.get(&(String::from("property"), None)) {
-            Meta {
-                name: String::from(prop),
-                content: String::from(chars),
-            }
-        // or the opf2 version
-        } else if let Some(name) = tag.attributes.get(&(String::from("name"), None)) {
-            if let Some(content) = tag.attributes.get(&(String::from("content"), None)) {
-                Meta {
-                    name: String::from(name),
-                    content: String::from(content),
+        let (name, content) =
+            if let Some(prop) = tag.attributes.get(&(String::from("property"), None)) {
+                (String::from(prop), String::from(chars.trim()))
+            // or the opf2 version
+            } else if let Some(name) = tag.attributes.get(&(String::from("name"), None)) {
+                if let Some(content) = tag.attributes.get(&(String::from("content"), None)) {
+                    (String::from(name), String::from(content))
+                } else {
+                    return Err(ParseError::missing_attribute(&tag.name, "content"));
                 }
             } else {
-                panic!();
+                return Err(ParseError::missing_attribute(&tag.name, "property"));
+            };
+        // a refines attribute is a relative url, normally a fragment
+        let refines = tag
+            .attributes
+            .get(&(String::from("refines"), None))
+            .map(|r| String::from(r.trim_start_matches('#')));
+        Ok(Meta {
+            name,
+            content,
+            id: tag.attributes.get(&(String::from("id"), None)).cloned(),
+            refines,
+            scheme: tag.attributes.get(&(String::from("scheme"), None)).cloned(),
+            lang: tag
+                .attributes
+                .get(&(String::from("lang"), Some(String::from(XML_NS))))
+                .cloned(),
+            refinements: Vec::new(),
+        })
+    }
+
+    /// the value of the first refinement with `property`
+    pub fn refinement(&self, property: &str) -> Option<&str> {
+        find_refinement(&self.refinements, property).map(|m| m.content.as_str())
+    }
+}
+
+/// the first meta in `refinements` with `property`
+fn find_refinement<'a>(refinements: &'a [Meta], property: &str) -> Option<&'a Meta> {
+    refinements.iter().find(|m| m.name == property)
+}
+
+/// the kind of a title, refinement `title-type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleType {
+    Main,
+    Subtitle,
+    Short,
+    Collection,
+    Edition,
+    Expanded,
+}
+
+impl TitleType {
+    /// parse the value of a `title-type` refinement
+    pub fn from_property(value: &str) -> Option<TitleType> {
+        match value {
+            "main" => Some(TitleType::Main),
+            "subtitle" => Some(TitleType::Subtitle),
+            "short" => Some(TitleType::Short),
+            "collection" => Some(TitleType::Collection),
+            "edition" => Some(TitleType::Edition),
+            "expanded" => Some(TitleType::Expanded),
+            _ => None,
+        }
+    }
+}
+
+/// namespace of the `xml:lang` attribute
+pub(crate) const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+/// namespace of the `epub:type` attribute
+pub(crate) const OPS_NS: &str = "http://www.idpf.org/2007/ops";
+/// namespace of the EPUB 2 `opf:` attributes
+const OPF_NS: &str = "http://www.idpf.org/2007/opf";
+
+/// text direction of an element, attribute `dir`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// left to right
+    Ltr,
+    /// right to left
+    Rtl,
+}
+
+impl Direction {
+    /// parse the value of a `dir` attribute
+    pub fn from_attribute(value: &str) -> Option<Direction> {
+        match value {
+            "ltr" => Some(Direction::Ltr),
+            "rtl" => Some(Direction::Rtl),
+            _ => None,
+        }
+    }
+}
+
+/// a Dublin Core element in the metadata section, such as `dc:title`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DcElement {
+    /// the text of the element
+    pub value: String,
+    /// attribute `id`
+    pub id: Option<String>,
+    /// attribute `xml:lang`
+    pub lang: Option<String>,
+    /// attribute `dir`
+    pub dir: Option<Direction>,
+    /// metas refining this element
+    pub refinements: Vec<Meta>,
+}
+
+impl DcElement {
+    /// create a new element from the xml tag and its text
+    pub fn new(tag: &StartTag, chars: &str) -> DcElement {
+        let dir = tag
+            .attributes
+            .get(&(String::from("dir"), None))
+            .and_then(|d| Direction::from_attribute(d));
+        // EPUB 2 puts these on the element, fold them into refinements so
+        // both versions read the same
+        let mut refinements = Vec::new();
+        for (attr, property, scheme) in &[
+            ("role", "role", Some("marc:relators")),
+            ("file-as", "file-as", None),
+            ("scheme", "identifier-type", None),
+            ("event", "event", None),
+        ] {
+            if let Some(val) = tag
+                .attributes
+                .get(&(String::from(*attr), Some(String::from(OPF_NS))))
+            {
+                refinements.push(Meta {
+                    name: String::from(*property),
+                    content: String::from(val.trim()),
+                    id: None,
+                    refines: None,
+                    scheme: scheme.map(String::from),
+                    lang: None,
+                    refinements: Vec::new(),
+                });
             }
-        } else {
-            panic!();
         }
+        DcElement {
+            value: String::from(chars.trim()),
+            id: tag.attributes.get(&(String::from("id"), None)).cloned(),
+            lang: tag
+                .attributes
+                .get(&(String::from("lang"), Some(String::from(XML_NS))))
+                .cloned(),
+            dir,
+            refinements,
+        }
+    }
+
+    /// the value of the first refinement with `property`
+    pub fn refinement(&self, property: &str) -> Option<&str> {
+        find_refinement(&self.refinements, property).map(|m| m.content.as_str())
+    }
+
+    /// refinement `title-type`
+    pub fn title_type(&self) -> Option<TitleType> {
+        self.refinement("title-type")
+            .and_then(TitleType::from_property)
+    }
+
+    /// refinement `file-as`, the form used for sorting
+    pub fn file_as(&self) -> Option<&str> {
+        self.refinement("file-as")
+    }
+
+    /// refinement `role`, a MARC relator code such as `aut`
+    pub fn role(&self) -> Option<&str> {
+        self.refinement("role")
+    }
+
+    /// refinement `identifier-type`, or `opf:scheme` in EPUB 2, such as `ISBN`
+    pub fn identifier_type(&self) -> Option<&str> {
+        self.refinement("identifier-type")
+    }
+
+    /// `opf:event` of an EPUB 2 `dc:date`, such as `publication` or
+    /// `modification`
+    pub fn event(&self) -> Option<&str> {
+        self.refinement("event")
+    }
+
+    /// refinement `display-seq`
+    pub fn display_seq(&self) -> Option<u32> {
+        self.refinement("display-seq")
+            .and_then(|s| s.trim().parse::<u32>().ok())
+    }
+
+    /// refinement `alternate-script`, the value in another language or script
+    pub fn alternate_script(&self) -> Option<&Meta> {
+        find_refinement(&self.refinements, "alternate-script")
+    }
+
+    /// the value to sort on, `file-as` if present
+    pub fn sort_key(&self) -> &str {
+        self.file_as().unwrap_or(&self.value)
     }
 }
 
 /// Metadata section from opf file
 #[derive(Debug)]
 pub struct Metadata {
-    /// package:unique-identifer
-    identifier: Identifier,
-    /// dc:title element
-    title: String,
-    /// dc::language
-    language: Vec<String>,
-    /// dc::contributor
-    contributor: Option<String>,
-    /// dc::coverage
-    coverage: Option<String>,
+    /// dc:identifier
+    identifiers: Vec<DcElement>,
+    /// dc:title
+    titles: Vec<DcElement>,
+    /// dc:language
+    languages: Vec<DcElement>,
+    /// dc:contributor
+    contributors: Vec<DcElement>,
+    /// dc:coverage
+    coverage: Option<DcElement>,
     /// dc:creator
-    creator: Vec<String>,
-    /// dc:date
-    date: Option<String>,
-    /// dc::description
-    description: Option<String>,
-    /// dc::format
-    format: Option<String>,
-    /// dc::publisher
-    publisher: Option<String>,
-    /// dc::relation
-    relation: Option<String>,
-    /// dc::rights
-    rights: Option<String>,
-    /// dc::source
-    source: Option<String>,
-    /// dc::subject
-    subject: Option<String>,
-    /// dc::type
-    metadata_type: Option<String>,
+    creators: Vec<DcElement>,
+    /// dc:date, an EPUB 2 book may have one for each `opf:event`
+    dates: Vec<DcElement>,
+    /// dc:description
+    description: Option<DcElement>,
+    /// dc:format
+    format: Option<DcElement>,
+    /// dc:publisher
+    publisher: Option<DcElement>,
+    /// dc:relation
+    relation: Option<DcElement>,
+    /// dc:rights
+    rights: Option<DcElement>,
+    /// dc:source
+    source: Option<DcElement>,
+    /// dc:subject
+    subjects: Vec<DcElement>,
+    /// dc:type
+    metadata_type: Option<DcElement>,
     /// list of `meta` tags
     meta_tags: Vec<Meta>,
+    /// the EPUB 3 `collection` elements of the package
+    collections: Vec<Collection>,
 }
 
 impl Metadata {
     /// create a new Metadata instance
     pub fn new() -> Metadata {
         Metadata {
-            identifier: Identifier::new(),
-            title: String::new(),
-            language: Vec::new(),
-            contributor: None,
+            identifiers: Vec::new(),
+            titles: Vec::new(),
+            languages: Vec::new(),
+            contributors: Vec::new(),
             coverage: None,
-            creator: Vec::new(),
-            date: None,
+            creators: Vec::new(),
+            dates: Vec::new(),
             description: None,
             format: None,
             publisher: None,
             relation: None,
             rights: None,
             source: None,
-            subject: None,
+            subjects: Vec::new(),
             metadata_type: None,
             meta_tags: Vec::new(),
+            collections: Vec::new(),
         }
     }
 
     /// add entry to the Metadata from xml tag
-    pub fn add_tag(&mut self, tag: &StartTag, chars: &str) {
+    pub fn add_tag(&mut self, tag: &StartTag, chars: &str) -> Result<(), ParseError> {
         trace!("metadata: '{}' with chars '{}'", tag.name, chars);
         for ((key1, key2), val) in &tag.attributes {
             trace!("attribute '{}:{:?}' is '{}'", key1, key2, val);
         }
+        if tag.name == "meta" {
+            self.meta_tags.push(Meta::new(tag, chars)?);
+            return Ok(());
+        }
+        let elem = DcElement::new(tag, chars);
         if tag.name == "identifier" {
-            self.identifier.add_tag(tag, chars);
+            self.identifiers.push(elem);
         } else if tag.name == "title" {
-            // has optional attributes dir,id,xml:lang
-            self.title += chars;
+            self.titles.push(elem);
         } else if tag.name == "language" {
-            // has optional attributes id
-            self.language.push(String::from(chars));
+            self.languages.push(elem);
+        } else if tag.name == "contributor" {
+            self.contributors.push(elem);
         } else if tag.name == "coverage" {
-            self.coverage = Some(String::from(chars));
+            self.coverage = Some(elem);
         } else if tag.name == "creator" {
-            self.creator.push(String::from(chars));
+            self.creators.push(elem);
         } else if tag.name == "date" {
-            self.date = Some(String::from(chars));
+            self.dates.push(elem);
         } else if tag.name == "description" {
-            self.description = Some(String::from(chars));
+            self.description = Some(elem);
         } else if tag.name == "format" {
-            self.format = Some(String::from(chars));
+            self.format = Some(elem);
         } else if tag.name == "publisher" {
-            self.publisher = Some(String::from(chars));
+            self.publisher = Some(elem);
         } else if tag.name == "relation" {
-            self.relation = Some(String::from(chars));
+            self.relation = Some(elem);
         } else if tag.name == "rights" {
-            self.rights = Some(String::from(chars));
+            self.rights = Some(elem);
         } else if tag.name == "source" {
-            self.source = Some(String::from(chars));
+            self.source = Some(elem);
         } else if tag.name == "subject" {
-            self.subject = Some(String::from(chars));
+            self.subjects.push(elem);
         } else if tag.name == "type" {
-            self.metadata_type = Some(String::from(chars));
-        } else if tag.name == "meta" {
-            self.meta_tags.push(Meta::new(tag, chars));
+            self.metadata_type = Some(elem);
         } else {
             warn!("Metadata unknown tag name: '{}'", tag.name);
         }
+        Ok(())
+    }
+
+    /// move each refining meta onto the element or meta it refines
+    ///
+    /// refinements of refinements are not followed
+    fn resolve_refinements(&mut self) {
+        let (refining, primary): (Vec<Meta>, Vec<Meta>) = core::mem::take(&mut self.meta_tags)
+            .into_iter()
+            .partition(|m| m.refines.is_some());
+        self.meta_tags = primary;
+        for meta in refining {
+            let target = meta.refines.clone().unwrap_or_default();
+            let target = Some(target.as_str());
+            let mut elems = self
+                .identifiers
+                .iter_mut()
+                .chain(self.titles.iter_mut())
+                .chain(self.languages.iter_mut())
+                .chain(self.contributors.iter_mut())
+                .chain(self.creators.iter_mut())
+                .chain(self.subjects.iter_mut())
+                .chain(self.coverage.iter_mut())
+                .chain(self.dates.iter_mut())
+                .chain(self.description.iter_mut())
+                .chain(self.format.iter_mut())
+                .chain(self.publisher.iter_mut())
+                .chain(self.relation.iter_mut())
+                .chain(self.rights.iter_mut())
+                .chain(self.source.iter_mut())
+                .chain(self.metadata_type.iter_mut());
+            if let Some(elem) = elems.find(|e| e.id.as_deref() == target) {
+                elem.refinements.push(meta);
+            } else if let Some(m) = self
+                .meta_tags
+                .iter_mut()
+                .find(|m| m.id.as_deref() == target)
+            {
+                m.refinements.push(meta);
+            } else {
+                warn!("meta '{}' refines unknown id {:?}", meta.name, meta.refines);
+            }
+        }
+    }
+
+    /// the main title, the title refined as `main`, or else the first title
+    pub fn title(&self) -> Option<&str> {
+        self.main_title().map(|t| t.value.as_str())
+    }
+
+    /// the `dc:title` element of the main title
+    pub fn main_title(&self) -> Option<&DcElement> {
+        self.titles
+            .iter()
+            .find(|t| t.title_type() == Some(TitleType::Main))
+            .or_else(|| self.titles.first())
+    }
+
+    /// the creators with MARC relator `role`, in display order
+    ///
+    /// creators with a `display-seq` come first in sequence, then the rest in
+    /// document order
+    pub fn creators_with_role(&self, role: &str) -> Vec<&DcElement> {
+        let mut creators: Vec<&DcElement> = self
+            .creators
+            .iter()
+            .filter(|c| c.role() == Some(role))
+            .collect();
+        creators.sort_by_key(|c| c.display_seq().unwrap_or(u32::MAX));
+        creators
+    }
+
+    /// the authors in display order
+    ///
+    /// creators with role `aut`, or every creator if none have a role
+    pub fn authors(&self) -> Vec<&DcElement> {
+        if self.creators.iter().any(|c| c.role().is_some()) {
+            self.creators_with_role("aut")
+        } else {
+            let mut creators: Vec<&DcElement> = self.creators.iter().collect();
+            creators.sort_by_key(|c| c.display_seq().unwrap_or(u32::MAX));
+            creators
+        }
+    }
+
+    /// the key to sort the book by author, the `file-as` of the first author
+    pub fn author_sort_key(&self) -> Option<&str> {
+        self.authors().first().map(|a| a.sort_key())
+    }
+
+    /// all `dc:title` elements in document order
+    pub fn titles(&self) -> &[DcElement] {
+        &self.titles
+    }
+
+    /// all `dc:creator` elements in document order
+    pub fn creators(&self) -> &[DcElement] {
+        &self.creators
+    }
+
+    /// all `dc:contributor` elements in document order
+    pub fn contributors(&self) -> &[DcElement] {
+        &self.contributors
+    }
+
+    /// the first language, the primary language of the book
+    pub fn language(&self) -> Option<&str> {
+        self.languages.first().map(|l| l.value.as_str())
+    }
+
+    /// all `dc:language` elements in document order
+    pub fn languages(&self) -> &[DcElement] {
+        &self.languages
+    }
+
+    /// all `dc:identifier` elements in document order
+    pub fn identifiers(&self) -> &[DcElement] {
+        &self.identifiers
+    }
+
+    /// the identifier with attribute `id`
+    pub fn identifier(&self, id: &str) -> Option<&DcElement> {
+        self.identifiers
+            .iter()
+            .find(|i| i.id.as_deref() == Some(id))
+    }
+
+    /// all `dc:subject` elements in document order
+    pub fn subjects(&self) -> &[DcElement] {
+        &self.subjects
+    }
+
+    /// `dc:coverage`
+    pub fn coverage(&self) -> Option<&DcElement> {
+        self.coverage.as_ref()
+    }
+
+    /// `dc:date`, the publication date
+    ///
+    /// the one with `opf:event` `publication`, or else the first without an
+    /// event, or else the first of any event
+    pub fn date(&self) -> Option<&DcElement> {
+        self.dates
+            .iter()
+            .find(|d| d.event() == Some("publication"))
+            .or_else(|| self.dates.iter().find(|d| d.event().is_none()))
+            .or_else(|| self.dates.first())
+    }
+
+    /// every `dc:date`
+    pub fn dates(&self) -> &[DcElement] {
+        &self.dates
+    }
+
+    /// the publication date, for sorting the library
+    pub fn publication_date(&self) -> Option<Date> {
+        self.date().and_then(|d| Date::parse(&d.value))
+    }
+
+    /// when the book was last modified, to tell an updated edition
+    ///
+    /// the `dcterms:modified` meta, or else the EPUB 2 `dc:date` with
+    /// `opf:event` `modification`
+    pub fn modified_date(&self) -> Option<Date> {
+        self.meta_tags
+            .iter()
+            .filter(|m| m.name == "dcterms:modified")
+            .find_map(|m| Date::parse(&m.content))
+            .or_else(|| {
+                self.dates
+                    .iter()
+                    .filter(|d| d.event() == Some("modification"))
+                    .find_map(|d| Date::parse(&d.value))
+            })
+    }
+
+    /// `dc:description`
+    pub fn description(&self) -> Option<&DcElement> {
+        self.description.as_ref()
+    }
+
+    /// `dc:format`
+    pub fn format(&self) -> Option<&DcElement> {
+        self.format.as_ref()
+    }
+
+    /// `dc:publisher`
+    pub fn publisher(&self) -> Option<&DcElement> {
+        self.publisher.as_ref()
+    }
+
+    /// `dc:relation`
+    pub fn relation(&self) -> Option<&DcElement> {
+        self.relation.as_ref()
+    }
+
+    /// `dc:rights`
+    pub fn rights(&self) -> Option<&DcElement> {
+        self.rights.as_ref()
+    }
+
+    /// `dc:source`
+    pub fn source(&self) -> Option<&DcElement> {
+        self.source.as_ref()
+    }
+
+    /// `dc:type`
+    pub fn metadata_type(&self) -> Option<&DcElement> {
+        self.metadata_type.as_ref()
+    }
+
+    /// the `meta` tags in document order, not counting refinements
+    pub fn meta_tags(&self) -> &[Meta] {
+        &self.meta_tags
+    }
+
+    /// the collections the book belongs to
+    ///
+    /// from the EPUB 3 `belongs-to-collection` metas, then the package
+    /// `collection` elements, then the calibre series metas of EPUB 2 books.
+    /// A calibre series already named by one of the others is left out.
+    pub fn collections(&self) -> Vec<Collection> {
+        let mut collections: Vec<Collection> = self
+            .meta_tags
+            .iter()
+            .filter(|m| m.name == "belongs-to-collection")
+            .map(Collection::from_meta)
+            .collect();
+        collections.extend(self.collections.iter().cloned());
+        // each series meta goes with the index meta in the same place
+        let calibre = self
+            .meta_tags
+            .iter()
+            .filter(|m| m.name == "calibre:series")
+            .enumerate()
+            .find(|(_, m)| !collections.iter().any(|c| c.name == m.content.trim()));
+        if let Some((n, series)) = calibre {
+            let index = self
+                .meta_tags
+                .iter()
+                .filter(|m| m.name == "calibre:series_index")
+                .nth(n);
+            collections.push(Collection {
+                name: String::from(series.content.trim()),
+                kind: Some(CollectionType::Series),
+                position: index.and_then(|m| parse_position(&m.content)),
+            });
+        }
+        collections
+    }
+
+    /// the series the book is in, for sorting the library
+    ///
+    /// the first collection of type `series`, or else the first one without
+    /// a type
+    pub fn series(&self) -> Option<Collection> {
+        let collections = self.collections();
+        let series = collections
+            .iter()
+            .position(|c| c.kind == Some(CollectionType::Series))
+            .or_else(|| collections.iter().position(|c| c.kind.is_none()))?;
+        collections.into_iter().nth(series)
+    }
+
+    /// add a package `collection` element, with the metadata inside it
+    fn add_collection(&mut self, role: Option<&str>, metadata: Metadata) {
+        let name = match metadata.title() {
+            Some(name) => String::from(name),
+            None => {
+                warn!("collection {:?} has no title", role);
+                return;
+            }
+        };
+        let position = metadata
+            .meta_tags
+            .iter()
+            .find(|m| m.name == "group-position")
+            .and_then(|m| parse_position(&m.content));
+        self.collections.push(Collection {
+            name,
+            kind: role.map(CollectionType::from_type),
+            position,
+        });
     }
 }
 
-/// dc::identifier
-#[derive(Debug)]
-pub struct Identifier {
-    id: String,
-    text: String,
+/// the kind of a collection, refinement `collection-type` or the role of
+/// a `collection` element
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CollectionType {
+    Series,
+    Set,
+    Other(String),
 }
 
-impl Identifier {
-    pub fn new() -> Identifier {
-        Identifier {
-            id: String::new(),
-            text: String::new(),
+impl CollectionType {
+    /// parse a `collection-type` value
+    pub fn from_type(value: &str) -> CollectionType {
+        match value.trim() {
+            "series" => CollectionType::Series,
+            "set" => CollectionType::Set,
+            other => CollectionType::Other(String::from(other)),
         }
     }
+}
 
-    pub fn add_tag(&mut self, tag: &StartTag, chars: &str) {
-        if let Some(id) = tag.attributes.get(&(String::from("id"), None)) {
-            self.id += id;
-            self.text += chars;
-        } else {
-            panic!();
+/// a series or set the book belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collection {
+    pub name: String,
+    /// `None` if the collection type is not given
+    pub kind: Option<CollectionType>,
+    /// the book's place in the collection, refinement `group-position` or
+    /// `calibre:series_index`, which may be fractional such as 1.5
+    pub position: Option<f64>,
+}
+
+impl Collection {
+    /// a collection from a `belongs-to-collection` meta and its refinements
+    fn from_meta(meta: &Meta) -> Collection {
+        Collection {
+            name: String::from(meta.content.trim()),
+            kind: meta
+                .refinement("collection-type")
+                .map(CollectionType::from_type),
+            position: meta.refinement("group-position").and_then(parse_position),
+        }
+    }
+}
+
+/// parse a position in a collection
+fn parse_position(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|p| p.is_finite())
+}
+
+/// the cover image of a book, see `Package::cover`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cover {
+    /// the image, relative to the container root
+    pub href: Href,
+    /// the image file on the filesystem
+    pub path: String,
+    pub media_type: String,
+}
+
+/// namespace of the `xlink:href` attribute of an SVG `image`
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+
+/// the source of the first `img`, or SVG `image`, in an XHTML document
+fn first_image(lines: &[String]) -> Option<String> {
+    let mut p = Parser::new();
+    for ln in lines {
+        p.feed_str(ln);
+        for event in &mut p {
+            match event {
+                Ok(Event::ElementStart(tag)) => {
+                    let src = match tag.name.as_str() {
+                        "img" => tag.attributes.get(&(String::from("src"), None)),
+                        "image" => tag
+                            .attributes
+                            .get(&(String::from("href"), Some(String::from(XLINK_NS))))
+                            .or_else(|| tag.attributes.get(&(String::from("href"), None))),
+                        _ => None,
+                    };
+                    if let Some(src) = src {
+                        return Some(src.clone());
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    warn!("Stopped looking for an image: {:?}", e);
+                    return None;
+                }
+            }
         }
     }
+    None
+}
+
+/// the core media types of EPUB 3, which a reading system must support
+/// without a fallback
+pub const CORE_MEDIA_TYPES: &[&str] = &[
+    "application/xhtml+xml",
+    "application/x-dtbncx+xml",
+    "application/smil+xml",
+    "application/pls+xml",
+    "application/javascript",
+    "text/javascript",
+    "text/css",
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "image/svg+xml",
+    "image/webp",
+    "audio/mpeg",
+    "audio/mp4",
+    "font/ttf",
+    "font/otf",
+    "font/woff",
+    "font/woff2",
+    "application/font-sfnt",
+    "application/vnd.ms-opentype",
+    "application/font-woff",
+];
+
+/// is `media_type` one of the EPUB 3 core media types
+pub fn is_core_media_type(media_type: &str) -> bool {
+    CORE_MEDIA_TYPES.contains(&media_type)
 }
 
 /// Manifest section of opf file
 #[derive(Debug)]
 pub struct Manifest {
-    pub items: Vec<Item>,
+    items: Vec<Item>,
+    /// path of the package document, item hrefs are relative to it
+    path: String,
+    /// index into `items` by id
+    by_id: BTreeMap<String, usize>,
+    /// index into `items` by href, resolved relative to the container root
+    by_path: BTreeMap<String, usize>,
 }
 
 impl Manifest {
-    /// create a new manifest
+    /// create a new manifest with hrefs relative to the container root
     pub fn new() -> Manifest {
-        Manifest { items: Vec::new() }
+        Manifest::with_path("")
+    }
+
+    /// create a new manifest for the package document at `opf_path`,
+    /// relative to the container root
+    pub fn with_path(opf_path: &str) -> Manifest {
+        Manifest {
+            items: Vec::new(),
+            path: String::from(opf_path),
+            by_id: BTreeMap::new(),
+            by_path: BTreeMap::new(),
+        }
     }
 
     /// add an item tag instance to the manifest
-    pub fn add_tag(&mut self, tag: &StartTag) {
-        self.items.push(Item::new(tag))
+    ///
+    /// an item with an id that is already in the manifest is an error, the
+    /// first item with an href is the one found by `item_at_path`
+    pub fn add_tag(&mut self, tag: &StartTag) -> Result<(), ParseError> {
+        let item = Item::new(tag)?;
+        if self.by_id.contains_key(&item.id) {
+            return Err(ParseError::duplicate_id(&tag.name, &item.id));
+        }
+        let index = self.items.len();
+        self.by_id.insert(item.id.clone(), index);
+        match io::resolve_href(&self.path, &item.href) {
+            Ok(href) => {
+                self.by_path.entry(href.path).or_insert(index);
+            }
+            Err(e) => warn!("item '{}' has a bad href: {:?}", item.id, e),
+        }
+        self.items.push(item);
+        Ok(())
+    }
+
+    /// every item, in document order
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /// the item with `id`
+    pub fn item(&self, id: &str) -> Option<&Item> {
+        self.by_id.get(id).map(|&i| &self.items[i])
+    }
+
+    /// the item at `path`, relative to the container root as in `Href::path`
+    pub fn item_at_path(&self, path: &str) -> Option<&Item> {
+        self.by_path.get(path).map(|&i| &self.items[i])
+    }
+
+    /// the first item with the property
+    pub fn item_with_property(&self, property: ItemProperty) -> Option<&Item> {
+        self.items.iter().find(|i| i.properties.contains(property))
+    }
+
+    /// the EPUB 3 navigation document
+    pub fn nav_item(&self) -> Option<&Item> {
+        self.item_with_property(ItemProperty::Nav)
+    }
+
+    /// the EPUB 3 cover image
+    pub fn cover_image_item(&self) -> Option<&Item> {
+        self.item_with_property(ItemProperty::CoverImage)
+    }
+
+    /// the media overlay of the item with `id`
+    pub fn media_overlay(&self, id: &str) -> Option<&Item> {
+        self.item(id)
+            .and_then(|i| i.media_overlay.as_deref())
+            .and_then(|mo| self.item(mo))
+    }
+
+    /// follow the fallback chain from the item with `id` to the first item
+    /// with a media type the reader supports
+    ///
+    /// chains that loop are cut off
+    pub fn resolve_fallback<F>(&self, id: &str, supported: F) -> Option<&Item>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut item = self.item(id)?;
+        for _ in 0..self.items.len() {
+            if supported(&item.media_type) {
+                return Some(item);
+            }
+            item = self.item(item.fallback.as_deref()?)?;
+        }
+        warn!("fallback chain from '{}' loops", id);
+        None
+    }
+
+    /// follow the fallback chain from the item with `id` to the first core
+    /// media type
+    pub fn core_fallback(&self, id: &str) -> Option<&Item> {
+        self.resolve_fallback(id, is_core_media_type)
+    }
+}
+
+/// a value of the `properties` attribute of a manifest item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemProperty {
+    Nav,
+    CoverImage,
+    Scripted,
+    Svg,
+    MathMl,
+    RemoteResources,
+    Switch,
+}
+
+impl ItemProperty {
+    /// parse a single property name
+    pub fn from_name(name: &str) -> Option<ItemProperty> {
+        match name {
+            "nav" => Some(ItemProperty::Nav),
+            "cover-image" => Some(ItemProperty::CoverImage),
+            "scripted" => Some(ItemProperty::Scripted),
+            "svg" => Some(ItemProperty::Svg),
+            "mathml" => Some(ItemProperty::MathMl),
+            "remote-resources" => Some(ItemProperty::RemoteResources),
+            "switch" => Some(ItemProperty::Switch),
+            _ => None,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// the set of properties of a manifest item
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ItemProperties(u8);
+
+impl ItemProperties {
+    /// parse the space separated `properties` attribute, unknown properties
+    /// are ignored
+    pub fn from_attribute(value: &str) -> ItemProperties {
+        let mut props = ItemProperties::default();
+        for name in value.split_ascii_whitespace() {
+            match ItemProperty::from_name(name) {
+                Some(prop) => props.insert(prop),
+                None => trace!("ignoring item property '{}'", name),
+            }
+        }
+        props
+    }
+
+    /// add a property
+    pub fn insert(&mut self, prop: ItemProperty) {
+        self.0 |= prop.bit();
+    }
+
+    /// is the property in the set
+    pub fn contains(&self, prop: ItemProperty) -> bool {
+        self.0 & prop.bit() != 0
+    }
+
+    /// no properties
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
     }
 }
 
@@ -368,37 +1418,191 @@ pub struct Item {
     pub id: String,
     pub href: String,
     pub media_type: String,
+    /// attribute `properties`
+    pub properties: ItemProperties,
+    /// attribute `fallback`, the id of the item to use instead
+    pub fallback: Option<String>,
+    /// attribute `media-overlay`, the id of the SMIL item
+    pub media_overlay: Option<String>,
 }
 
 impl Item {
     /// create a new item from the item tag
-    pub fn new(tag: &StartTag) -> Item {
+    pub fn new(tag: &StartTag) -> Result<Item, ParseError> {
         if let Some(id) = tag.attributes.get(&(String::from("id"), None)) {
             if let Some(href) = tag.attributes.get(&(String::from("href"), None)) {
                 if let Some(mtype) = tag.attributes.get(&(String::from("media-type"), None)) {
                     trace!("item {} ref='{}' m='{}'", id, href, mtype);
-                    Item {
+                    let properties = tag
+                        .attributes
+                        .get(&(String::from("properties"), None))
+                        .map(|p| ItemProperties::from_attribute(p))
+                        .unwrap_or_default();
+                    Ok(Item {
                         id: String::from(id),
                         href: String::from(href),
                         media_type: String::from(mtype),
-                    }
+                        properties,
+                        fallback: tag
+                            .attributes
+                            .get(&(String::from("fallback"), None))
+                            .cloned(),
+                        media_overlay: tag
+                            .attributes
+                            .get(&(String::from("media-overlay"), None))
+                            .cloned(),
+                    })
                 } else {
-                    panic!();
+                    Err(ParseError::missing_attribute(&tag.name, "media-type"))
                 }
             } else {
-                panic!();
+                Err(ParseError::missing_attribute(&tag.name, "href"))
             }
         } else {
-            panic!();
+            Err(ParseError::missing_attribute(&tag.name, "id"))
         }
     }
+
+    /// is the item an image
+    pub fn is_image(&self) -> bool {
+        self.media_type.starts_with("image/")
+    }
+}
+
+/// the kind of a landmark, from the EPUB 3 `epub:type` or the EPUB 2 guide
+/// `type`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LandmarkType {
+    Cover,
+    TitlePage,
+    Toc,
+    Frontmatter,
+    /// the start of the main content, `text` in the EPUB 2 guide
+    Bodymatter,
+    Backmatter,
+    CopyrightPage,
+    Dedication,
+    Epigraph,
+    Foreword,
+    Preface,
+    Acknowledgements,
+    Bibliography,
+    Glossary,
+    Index,
+    Notes,
+    Colophon,
+    /// list of illustrations
+    Loi,
+    /// list of tables
+    Lot,
+    Other(String),
+}
+
+impl LandmarkType {
+    /// parse an `epub:type` or guide `type` value
+    pub fn from_type(value: &str) -> LandmarkType {
+        match value {
+            "cover" => LandmarkType::Cover,
+            "title-page" | "titlepage" => LandmarkType::TitlePage,
+            "toc" => LandmarkType::Toc,
+            "frontmatter" => LandmarkType::Frontmatter,
+            "text" | "bodymatter" => LandmarkType::Bodymatter,
+            "backmatter" => LandmarkType::Backmatter,
+            "copyright-page" => LandmarkType::CopyrightPage,
+            "dedication" => LandmarkType::Dedication,
+            "epigraph" => LandmarkType::Epigraph,
+            "foreword" => LandmarkType::Foreword,
+            "preface" => LandmarkType::Preface,
+            "acknowledgements" | "acknowledgments" => LandmarkType::Acknowledgements,
+            "bibliography" => LandmarkType::Bibliography,
+            "glossary" => LandmarkType::Glossary,
+            "index" => LandmarkType::Index,
+            "notes" | "endnotes" | "footnotes" => LandmarkType::Notes,
+            "colophon" => LandmarkType::Colophon,
+            "loi" => LandmarkType::Loi,
+            "lot" => LandmarkType::Lot,
+            other => LandmarkType::Other(String::from(other)),
+        }
+    }
+
+    /// pages a reader skips when opening a book for the first time
+    pub fn is_preliminary(&self) -> bool {
+        matches!(
+            self,
+            LandmarkType::Cover
+                | LandmarkType::TitlePage
+                | LandmarkType::CopyrightPage
+                | LandmarkType::Toc
+        )
+    }
+}
+
+/// a landmark in the book, from the EPUB 3 `landmarks` nav or an EPUB 2
+/// guide `reference`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Landmark {
+    pub kind: LandmarkType,
+    pub title: String,
+    /// the target, relative to the container root
+    pub href: Href,
+}
+
+impl Landmark {
+    /// create a landmark from a guide `reference` tag in the package
+    /// document at `opf_path`
+    ///
+    /// a reference with an href that can't be resolved is skipped
+    pub fn from_reference(tag: &StartTag, opf_path: &str) -> Result<Option<Landmark>, ParseError> {
+        let kind = match tag.attributes.get(&(String::from("type"), None)) {
+            Some(t) => LandmarkType::from_type(t.trim()),
+            None => return Err(ParseError::missing_attribute(&tag.name, "type")),
+        };
+        let href = match tag.attributes.get(&(String::from("href"), None)) {
+            Some(h) => h,
+            None => return Err(ParseError::missing_attribute(&tag.name, "href")),
+        };
+        let href = match io::resolve_href(opf_path, href) {
+            Ok(href) => href,
+            Err(e) => {
+                warn!("Skipping guide reference '{}': {:?}", href, e);
+                return Ok(None);
+            }
+        };
+        Ok(Some(Landmark {
+            kind,
+            title: tag
+                .attributes
+                .get(&(String::from("title"), None))
+                .cloned()
+                .unwrap_or_default(),
+            href,
+        }))
+    }
+}
+
+/// a location in the book, a spine item and an optional fragment id in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpinePosition {
+    /// index into `Spine::itemrefs`
+    pub index: usize,
+    pub fragment: Option<String>,
+}
+
+/// the side of the screen a page turn was asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSide {
+    Left,
+    Right,
 }
 
 /// spine section from opf file
 #[derive(Debug)]
 pub struct Spine {
     pub itemrefs: Vec<ItemRef>,
-    pub toc: String,
+    /// attribute `toc`, the id of the NCX item, EPUB 3 books may not have one
+    pub toc: Option<String>,
+    /// attribute `page-progression-direction`, `None` is the default
+    pub page_progression_direction: Option<Direction>,
 }
 
 impl Spine {
@@ -406,42 +1610,157 @@ impl Spine {
     pub fn new() -> Spine {
         Spine {
             itemrefs: Vec::new(),
-            toc: String::new(),
+            toc: None,
+            page_progression_direction: None,
         }
     }
 
     /// add an itemref tag instance to the spine
-    pub fn add_tag(&mut self, tag: &StartTag) {
+    pub fn add_tag(&mut self, tag: &StartTag) -> Result<(), ParseError> {
         if tag.name == "spine" {
-            if let Some(toc) = tag.attributes.get(&(String::from("toc"), None)) {
-                self.toc += toc;
-            } else {
-                panic!();
-            }
+            self.page_progression_direction = tag
+                .attributes
+                .get(&(String::from("page-progression-direction"), None))
+                .and_then(|d| Direction::from_attribute(d));
+            self.toc = tag.attributes.get(&(String::from("toc"), None)).cloned();
         } else {
-            self.itemrefs.push(ItemRef::new(tag))
+            self.itemrefs.push(ItemRef::new(tag)?)
         }
+        Ok(())
+    }
+
+    /// do pages progress from right to left
+    pub fn is_rtl(&self) -> bool {
+        self.page_progression_direction == Some(Direction::Rtl)
     }
+
+    /// the index of the next linear itemref after `idx`
+    pub fn next(&self, idx: usize) -> Option<usize> {
+        self.next_with(idx, false)
+    }
+
+    /// the index of the next itemref after `idx`, including non-linear ones
+    /// if `nonlinear`
+    pub fn next_with(&self, idx: usize, nonlinear: bool) -> Option<usize> {
+        self.itemrefs
+            .iter()
+            .enumerate()
+            .skip(idx + 1)
+            .find(|(_, r)| nonlinear || r.linear)
+            .map(|(i, _)| i)
+    }
+
+    /// the index of the previous linear itemref before `idx`
+    pub fn previous(&self, idx: usize) -> Option<usize> {
+        self.previous_with(idx, false)
+    }
+
+    /// the index of the previous itemref before `idx`, including non-linear
+    /// ones if `nonlinear`
+    pub fn previous_with(&self, idx: usize, nonlinear: bool) -> Option<usize> {
+        self.itemrefs
+            .iter()
+            .enumerate()
+            .take(idx)
+            .rev()
+            .find(|(_, r)| nonlinear || r.linear)
+            .map(|(i, _)| i)
+    }
+
+    /// the index of the first linear itemref
+    pub fn first(&self) -> Option<usize> {
+        self.itemrefs.iter().position(|r| r.linear)
+    }
+
+    /// the itemref to show for a page turn toward `side`
+    ///
+    /// turning right goes forward in a left to right book and back in a
+    /// right to left one
+    pub fn turn_page(&self, idx: usize, side: PageSide) -> Option<usize> {
+        let forward = match side {
+            PageSide::Right => !self.is_rtl(),
+            PageSide::Left => self.is_rtl(),
+        };
+        if forward {
+            self.next(idx)
+        } else {
+            self.previous(idx)
+        }
+    }
+}
+
+/// a spread placement from the itemref `properties`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSpread {
+    Left,
+    Right,
+    Center,
 }
 
 /// itemref tag of opf file
 #[derive(Debug)]
 pub struct ItemRef {
     pub idref: String,
+    /// attribute `id`
+    pub id: Option<String>,
+    /// attribute `linear`, false for auxiliary content such as footnotes
+    pub linear: bool,
+    /// attribute `properties`, such as `page-spread-left` or the
+    /// `rendition:` overrides
+    pub properties: Vec<String>,
 }
 
 impl ItemRef {
     /// create a new itemref from the itemref tag
-    pub fn new(tag: &StartTag) -> ItemRef {
+    pub fn new(tag: &StartTag) -> Result<ItemRef, ParseError> {
         if let Some(id) = tag.attributes.get(&(String::from("idref"), None)) {
             trace!("itemref {}", id);
-            ItemRef {
+            let linear = tag
+                .attributes
+                .get(&(String::from("linear"), None))
+                .map(|l| l.trim() != "no")
+                .unwrap_or(true);
+            let properties = tag
+                .attributes
+                .get(&(String::from("properties"), None))
+                .map(|p| p.split_ascii_whitespace().map(String::from).collect())
+                .unwrap_or_default();
+            Ok(ItemRef {
                 idref: String::from(id),
-            }
+                id: tag.attributes.get(&(String::from("id"), None)).cloned(),
+                linear,
+                properties,
+            })
         } else {
-            panic!();
+            Err(ParseError::missing_attribute(&tag.name, "idref"))
         }
     }
+
+    /// does the itemref have the property
+    pub fn has_property(&self, property: &str) -> bool {
+        self.properties.iter().any(|p| p == property)
+    }
+
+    /// the spread placement of the page
+    pub fn page_spread(&self) -> Option<PageSpread> {
+        self.properties
+            .iter()
+            .find_map(|p| match p.trim_start_matches("rendition:") {
+                "page-spread-left" => Some(PageSpread::Left),
+                "page-spread-right" => Some(PageSpread::Right),
+                "page-spread-center" => Some(PageSpread::Center),
+                _ => None,
+            })
+    }
+
+    /// the `rendition:` overrides other than the spread placement, such as
+    /// `rendition:layout-pre-paginated`
+    pub fn rendition_overrides(&self) -> impl Iterator<Item = &str> {
+        self.properties
+            .iter()
+            .map(|p| p.as_str())
+            .filter(|p| p.starts_with("rendition:") && !p.starts_with("rendition:page-spread-"))
+    }
 }
 
 #[cfg(test)]
@@ -468,8 +1787,8 @@ mod tests {
                         info!("attribute '{}:{:?}' is '{}'", key1, key2, val);
                     }
                     let (s1, s2, s3) = Package::collect_attributes(&tag);
-                    assert_eq!(s1, "p9781718500457");
-                    assert_eq!(s2, "3.0");
+                    assert_eq!(s1.unwrap(), "p9781718500457");
+                    assert_eq!(s2.unwrap(), "3.0");
                     assert_eq!(s3.unwrap(), "en");
                 }
                 _ => (),
@@ -488,11 +1807,11 @@ mod tests {
         // get events for the fed data
         for event in p {
             match event.unwrap() {
-                xml::Event::ElementStart(tag) => manifest.add_tag(&tag),
+                xml::Event::ElementStart(tag) => manifest.add_tag(&tag).unwrap(),
                 _ => (),
             }
         }
-        assert_eq!(manifest.items.len(), 1);
+        assert_eq!(manifest.items().len(), 1);
     }
 
     #[test]
@@ -507,7 +1826,7 @@ mod tests {
         for event in p {
             match event.unwrap() {
                 xml::Event::ElementStart(tag) => {
-                    let itm = Item::new(&tag);
+                    let itm = Item::new(&tag).unwrap();
                     assert_eq!(itm.id, "ncxtoc");
                     assert_eq!(itm.media_type, "application/x-dtbncx+xml");
                     assert_eq!(itm.href, "toc.ncx");
@@ -518,7 +1837,57 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    fn test_item_properties() {
+        let mut p = xml::Parser::new();
+        p.feed_str(
+            "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"scripted nav  unknown\" media-overlay=\"nav-mo\"/>",
+        );
+        for event in p {
+            match event.unwrap() {
+                xml::Event::ElementStart(tag) => {
+                    let itm = Item::new(&tag).unwrap();
+                    assert!(itm.properties.contains(ItemProperty::Nav));
+                    assert!(itm.properties.contains(ItemProperty::Scripted));
+                    assert!(!itm.properties.contains(ItemProperty::CoverImage));
+                    assert_eq!(itm.media_overlay.as_deref(), Some("nav-mo"));
+                    assert_eq!(itm.fallback, None);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fallback() {
+        let mut p = xml::Parser::new();
+        p.feed_str(
+            "<manifest>
+<item id=\"a\" href=\"a.dtb\" media-type=\"application/x-dtbook+xml\" fallback=\"b\"/>
+<item id=\"b\" href=\"b.svg\" media-type=\"image/svg+xml\" fallback=\"c\"/>
+<item id=\"c\" href=\"c.xhtml\" media-type=\"application/xhtml+xml\" properties=\"cover-image\"/>
+<item id=\"x\" href=\"x.foo\" media-type=\"application/foo\" fallback=\"y\"/>
+<item id=\"y\" href=\"y.foo\" media-type=\"application/foo\" fallback=\"x\"/>
+</manifest>",
+        );
+        let mut manifest = Manifest::new();
+        for event in p {
+            match event.unwrap() {
+                xml::Event::ElementStart(tag) if tag.name == "item" => {
+                    manifest.add_tag(&tag).unwrap()
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(manifest.core_fallback("a").unwrap().id, "b");
+        let xhtml_only = |mt: &str| mt == "application/xhtml+xml";
+        assert_eq!(manifest.resolve_fallback("a", xhtml_only).unwrap().id, "c");
+        assert!(manifest.core_fallback("x").is_none());
+        assert!(manifest.core_fallback("missing").is_none());
+        assert_eq!(manifest.cover_image_item().unwrap().id, "c");
+        assert!(manifest.nav_item().is_none());
+    }
+
+    #[test]
     fn test_baditem() {
         let mut p = xml::Parser::new();
 
@@ -529,7 +1898,9 @@ mod tests {
         for event in p {
             match event.unwrap() {
                 xml::Event::ElementStart(tag) => {
-                    let _itm = Item::new(&tag);
+                    let err = Item::new(&tag).unwrap_err();
+                    assert_eq!(err.element, "item");
+                    assert_eq!(err.kind, ParseErrorKind::MissingAttribute("media-type"));
                 }
                 _ => (),
             }
@@ -545,7 +1916,7 @@ mod tests {
         // get events for the fed data
         for event in p {
             match event.unwrap() {
-                xml::Event::ElementStart(tag) => spine.add_tag(&tag),
+                xml::Event::ElementStart(tag) => spine.add_tag(&tag).unwrap(),
                 _ => (),
             }
         }
@@ -562,7 +1933,7 @@ mod tests {
         for event in p {
             match event.unwrap() {
                 xml::Event::ElementStart(tag) => {
-                    let itmref = ItemRef::new(&tag);
+                    let itmref = ItemRef::new(&tag).unwrap();
                     assert_eq!(itmref.idref, "copy");
                 }
                 _ => (),
@@ -571,7 +1942,43 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    fn test_spine_navigation() {
+        let mut p = xml::Parser::new();
+        p.feed_str(
+            "<spine toc=\"ncx\" page-progression-direction=\"rtl\">
+<itemref idref=\"c1\" properties=\"page-spread-right rendition:layout-pre-paginated\"/>
+<itemref idref=\"notes\" linear=\"no\" id=\"n\"/>
+<itemref idref=\"c2\" linear=\"yes\" properties=\"rendition:page-spread-center\"/>
+<itemref idref=\"answers\" linear=\"no\"/>
+</spine>",
+        );
+        let mut spine = Spine::new();
+        for event in p {
+            match event.unwrap() {
+                xml::Event::ElementStart(tag) => spine.add_tag(&tag).unwrap(),
+                _ => (),
+            }
+        }
+        assert!(spine.is_rtl());
+        assert_eq!(spine.itemrefs.len(), 4);
+        assert!(!spine.itemrefs[1].linear);
+        assert_eq!(spine.itemrefs[1].id.as_deref(), Some("n"));
+        assert_eq!(spine.itemrefs[0].page_spread(), Some(PageSpread::Right));
+        assert_eq!(spine.itemrefs[2].page_spread(), Some(PageSpread::Center));
+        let overrides: Vec<&str> = spine.itemrefs[0].rendition_overrides().collect();
+        assert_eq!(overrides, ["rendition:layout-pre-paginated"]);
+        assert_eq!(spine.first(), Some(0));
+        assert_eq!(spine.next(0), Some(2));
+        assert_eq!(spine.next(2), None);
+        assert_eq!(spine.next_with(0, true), Some(1));
+        assert_eq!(spine.previous(2), Some(0));
+        assert_eq!(spine.previous(0), None);
+        // right to left, so the left side goes forward
+        assert_eq!(spine.turn_page(0, PageSide::Left), Some(2));
+        assert_eq!(spine.turn_page(2, PageSide::Right), Some(0));
+    }
+
+    #[test]
     fn test_baditemref() {
         let mut p = xml::Parser::new();
         // feed data to be parsed
@@ -581,10 +1988,539 @@ mod tests {
         for event in p {
             match event.unwrap() {
                 xml::Event::ElementStart(tag) => {
-                    let _itmref = ItemRef::new(&tag);
+                    let err = ItemRef::new(&tag).unwrap_err();
+                    assert_eq!(err.element, "itemref");
+                    assert_eq!(err.kind, ParseErrorKind::MissingAttribute("idref"));
                 }
                 _ => (),
             }
         }
     }
+
+    type IO = fatfs::StdIoWrapper<std::fs::File>;
+
+    const BAD_OPF: &str = "<?xml version=\"1.0\"?>
+<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"2.0\" unique-identifier=\"uid\">
+<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+<dc:identifier id=\"uid\">urn:isbn:123</dc:identifier>
+<dc:title>Title</dc:title>
+<meta name=\"cover\"/>
+</metadata>
+<manifest>
+<item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>
+<item id=\"ch1\" href=\"ch1.xhtml\"/>
+<item id=\"ch2\" href=\"ch2.xhtml\" media-type=\"application/xhtml+xml\"/>
+</manifest>
+<spine toc=\"ncx\">
+<itemref idref=\"ch2\"/>
+</spine>
+</package>
+";
+
+    fn rootfile() -> Rootfile {
+        Rootfile {
+            full_path: String::from("book/OEBPS/content.opf"),
+            media_type: String::from("application/oebps-package+xml"),
+            root_dir: String::from("book"),
+            path: String::from("OEBPS/content.opf"),
+        }
+    }
+
+    fn lines(doc: &str) -> Vec<String> {
+        doc.split_inclusive('\n').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        match Package::parse::<IO>(&lines(BAD_OPF), &rootfile(), ParseMode::Strict) {
+            Err(EPubError::Parse(e)) => {
+                assert_eq!(e.file, "book/OEBPS/content.opf");
+                assert_eq!(e.element, "meta");
+                assert_eq!(e.kind, ParseErrorKind::MissingAttribute("content"));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        let pkg = Package::parse::<IO>(&lines(BAD_OPF), &rootfile(), ParseMode::Lenient).unwrap();
+        assert_eq!(pkg.unique_identifer, "uid");
+        assert_eq!(pkg.manifest.items().len(), 2);
+        assert_eq!(pkg.spine.itemrefs.len(), 1);
+        assert_eq!(pkg.warnings.len(), 2);
+        assert_eq!(pkg.warnings[1].element, "item");
+        assert_eq!(
+            pkg.warnings[1].kind,
+            ParseErrorKind::MissingAttribute("media-type")
+        );
+    }
+
+    const METADATA_OPF: &str = "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"pub-id\">
+<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+<dc:identifier id=\"isbn\">urn:isbn:9780000000001</dc:identifier>
+<dc:identifier id=\"pub-id\">urn:uuid:1234</dc:identifier>
+<dc:title id=\"t1\" xml:lang=\"en\" dir=\"ltr\">The Book</dc:title>
+<dc:title id=\"t2\">A Subtitle</dc:title>
+<dc:creator id=\"c1\">First Author</dc:creator>
+<dc:creator>Second Author</dc:creator>
+<dc:language>en</dc:language>
+<dc:language>fr</dc:language>
+<dc:subject>Fiction</dc:subject>
+<dc:subject>Fantasy</dc:subject>
+<dc:publisher xml:lang=\"ar\" dir=\"rtl\">Publisher</dc:publisher>
+<dc:date>2020-01-01</dc:date>
+</metadata>
+<spine toc=\"ncx\"></spine>
+</package>
+";
+
+    #[test]
+    fn test_metadata() {
+        let pkg =
+            Package::parse::<IO>(&lines(METADATA_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        let md = &pkg.metadata;
+        assert_eq!(md.title(), Some("The Book"));
+        assert_eq!(md.titles().len(), 2);
+        assert_eq!(md.titles()[0].id.as_deref(), Some("t1"));
+        assert_eq!(md.titles()[0].lang.as_deref(), Some("en"));
+        assert_eq!(md.titles()[0].dir, Some(Direction::Ltr));
+        assert_eq!(md.titles()[1].lang, None);
+        let creators: Vec<&str> = md.creators().iter().map(|c| c.value.as_str()).collect();
+        assert_eq!(creators, ["First Author", "Second Author"]);
+        assert_eq!(md.language(), Some("en"));
+        assert_eq!(md.languages().len(), 2);
+        assert_eq!(md.subjects().len(), 2);
+        assert_eq!(md.publisher().unwrap().dir, Some(Direction::Rtl));
+        assert_eq!(md.date().unwrap().value, "2020-01-01");
+        assert_eq!(md.description(), None);
+        assert_eq!(pkg.identifier().unwrap().value, "urn:uuid:1234");
+    }
+
+    const REFINES_OPF: &str =
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"uid\">
+<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+<dc:identifier id=\"uid\">urn:uuid:1234</dc:identifier>
+<dc:title id=\"sub\">A Subtitle</dc:title>
+<meta refines=\"#sub\" property=\"title-type\">subtitle</meta>
+<dc:title id=\"main\">The Book</dc:title>
+<meta refines=\"#main\" property=\"title-type\">main</meta>
+<meta refines=\"#main\" property=\"alternate-script\" xml:lang=\"ja\">本</meta>
+<dc:creator id=\"ill\">An Illustrator</dc:creator>
+<meta refines=\"#ill\" property=\"role\" scheme=\"marc:relators\">ill</meta>
+<dc:creator id=\"a2\">Second Author</dc:creator>
+<meta refines=\"#a2\" property=\"role\" scheme=\"marc:relators\">aut</meta>
+<meta refines=\"#a2\" property=\"display-seq\">2</meta>
+<dc:creator id=\"a1\">First Author</dc:creator>
+<meta refines=\"#a1\" property=\"role\" scheme=\"marc:relators\">aut</meta>
+<meta refines=\"#a1\" property=\"file-as\">Author, First</meta>
+<meta refines=\"#a1\" property=\"display-seq\">1</meta>
+<meta property=\"belongs-to-collection\" id=\"c1\">The Series</meta>
+<meta refines=\"#c1\" property=\"group-position\">3</meta>
+<meta property=\"dcterms:modified\">2020-01-01T00:00:00Z</meta>
+</metadata>
+<spine toc=\"ncx\"></spine>
+</package>
+";
+
+    #[test]
+    fn test_refines() {
+        let pkg =
+            Package::parse::<IO>(&lines(REFINES_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        let md = &pkg.metadata;
+        assert_eq!(md.title(), Some("The Book"));
+        assert_eq!(md.titles()[0].title_type(), Some(TitleType::Subtitle));
+        let alt = md.main_title().unwrap().alternate_script().unwrap();
+        assert_eq!(alt.content, "本");
+        assert_eq!(alt.lang.as_deref(), Some("ja"));
+        let authors: Vec<&str> = md.authors().iter().map(|a| a.value.as_str()).collect();
+        assert_eq!(authors, ["First Author", "Second Author"]);
+        assert_eq!(md.author_sort_key(), Some("Author, First"));
+        assert_eq!(md.creators_with_role("ill").len(), 1);
+        assert_eq!(
+            md.creators()[0].refinements[0].scheme.as_deref(),
+            Some("marc:relators")
+        );
+        // refinements are moved off the list of metas
+        assert_eq!(md.meta_tags().len(), 2);
+        assert_eq!(md.meta_tags()[0].refinement("group-position"), Some("3"));
+    }
+
+    #[test]
+    fn test_dates() {
+        let pkg =
+            Package::parse::<IO>(&lines(REFINES_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        let modified = pkg.metadata.modified_date().unwrap();
+        assert_eq!(modified, Date::parse("2020-01-01T00:00:00Z").unwrap());
+        assert!(pkg.metadata.publication_date().is_none());
+        let opf = "<package xmlns=\"http://www.idpf.org/2007/opf\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\" version=\"2.0\" unique-identifier=\"uid\">
+<metadata>
+<dc:date opf:event=\"creation\">2001-03</dc:date>
+<dc:date opf:event=\"modification\">2011-05-23T08:00:00+02:00</dc:date>
+<dc:date opf:event=\"publication\">2002</dc:date>
+</metadata>
+</package>
+";
+        let pkg = Package::parse::<IO>(&lines(opf), &rootfile(), ParseMode::Strict).unwrap();
+        let md = &pkg.metadata;
+        assert_eq!(md.dates().len(), 3);
+        assert_eq!(md.dates()[0].event(), Some("creation"));
+        assert_eq!(md.date().unwrap().value, "2002");
+        assert_eq!(md.publication_date().unwrap().year, 2002);
+        let modified = md.modified_date().unwrap();
+        assert_eq!(modified.timestamp(), 1_306_130_400);
+        assert!(md.publication_date() < Some(modified));
+        // only dates with other events
+        let opf = "<package xmlns=\"http://www.idpf.org/2007/opf\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\" version=\"2.0\" unique-identifier=\"uid\">
+<metadata>
+<dc:date opf:event=\"original-publication\">1851</dc:date>
+<dc:date opf:event=\"creation\">2001-03</dc:date>
+</metadata>
+</package>
+";
+        let pkg = Package::parse::<IO>(&lines(opf), &rootfile(), ParseMode::Strict).unwrap();
+        assert_eq!(pkg.metadata.date().unwrap().value, "1851");
+        assert_eq!(pkg.metadata.publication_date().unwrap().year, 1851);
+    }
+
+    #[test]
+    fn test_manifest_index() {
+        let opf = "<package version=\"3.0\" unique-identifier=\"uid\">
+<manifest>
+<item id=\"ch1\" href=\"text/ch%201.xhtml\" media-type=\"application/xhtml+xml\"/>
+<item id=\"ch2\" href=\"./text/../text/ch2.xhtml\" media-type=\"application/xhtml+xml\"/>
+<item id=\"ch1\" href=\"text/dup.xhtml\" media-type=\"application/xhtml+xml\"/>
+</manifest>
+<spine>
+<itemref idref=\"ch2\"/>
+<itemref idref=\"ch1\"/>
+<itemref idref=\"missing\"/>
+</spine>
+</package>
+";
+        match Package::parse::<IO>(&lines(opf), &rootfile(), ParseMode::Strict) {
+            Err(EPubError::Parse(e)) => {
+                assert_eq!(e.element, "item");
+                assert_eq!(e.kind, ParseErrorKind::DuplicateId(String::from("ch1")));
+            }
+            _ => panic!(),
+        }
+        let pkg = Package::parse::<IO>(&lines(opf), &rootfile(), ParseMode::Lenient).unwrap();
+        // the duplicate is skipped
+        assert_eq!(pkg.warnings.len(), 1);
+        let manifest = &pkg.manifest;
+        assert_eq!(manifest.items().len(), 2);
+        assert!(manifest.item_at_path("OEBPS/text/dup.xhtml").is_none());
+        assert_eq!(manifest.item("ch1").unwrap().href, "text/ch%201.xhtml");
+        assert!(manifest.item("missing").is_none());
+        assert_eq!(
+            manifest.item_at_path("OEBPS/text/ch 1.xhtml").unwrap().id,
+            "ch1"
+        );
+        assert_eq!(
+            manifest.item_at_path("OEBPS/text/ch2.xhtml").unwrap().id,
+            "ch2"
+        );
+        let href = pkg.resolve("text/ch2.xhtml#top").unwrap();
+        assert_eq!(pkg.item_at(&href).unwrap().id, "ch2");
+        let (item, path) = pkg.spine_item(1).unwrap();
+        assert_eq!(item.id, "ch1");
+        assert_eq!(path, "book/OEBPS/text/ch 1.xhtml");
+        assert!(pkg.spine_item(2).is_none());
+        assert!(pkg.spine_item(3).is_none());
+    }
+
+    const SERIES_OPF: &str =
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" version=\"3.0\" unique-identifier=\"uid\">
+<metadata>
+<dc:title>The Book</dc:title>
+<meta property=\"belongs-to-collection\" id=\"c1\">Great Novels</meta>
+<meta refines=\"#c1\" property=\"collection-type\">set</meta>
+<meta property=\"belongs-to-collection\" id=\"c2\">The Series</meta>
+<meta refines=\"#c2\" property=\"collection-type\">series</meta>
+<meta refines=\"#c2\" property=\"group-position\">2.5</meta>
+<meta name=\"calibre:series\" content=\"The Series\"/>
+<meta name=\"calibre:series_index\" content=\"2.5\"/>
+</metadata>
+<spine></spine>
+<collection role=\"index\">
+<metadata>
+<dc:title>Index</dc:title>
+<meta property=\"group-position\">1</meta>
+</metadata>
+<collection role=\"index-group\"><metadata><dc:title>Inner</dc:title></metadata></collection>
+<link href=\"index.xhtml\"/>
+</collection>
+</package>
+";
+
+    #[test]
+    fn test_collections() {
+        let pkg = Package::parse::<IO>(&lines(SERIES_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        let md = &pkg.metadata;
+        // the collection's metadata is not the book's
+        assert_eq!(md.titles().len(), 1);
+        let collections = md.collections();
+        assert_eq!(collections.len(), 3);
+        assert_eq!(collections[0].name, "Great Novels");
+        assert_eq!(collections[0].kind, Some(CollectionType::Set));
+        assert_eq!(collections[0].position, None);
+        assert_eq!(collections[2].name, "Index");
+        assert_eq!(
+            collections[2].kind,
+            Some(CollectionType::Other(String::from("index")))
+        );
+        assert_eq!(collections[2].position, Some(1.0));
+        let series = md.series().unwrap();
+        assert_eq!(series.name, "The Series");
+        assert_eq!(series.position, Some(2.5));
+    }
+
+    #[test]
+    fn test_calibre_series() {
+        let opf = "<package version=\"2.0\" unique-identifier=\"uid\">
+<metadata>
+<meta name=\"calibre:series_index\" content=\"4\"/>
+<meta name=\"calibre:series\" content=\" Saga \"/>
+</metadata>
+</package>
+";
+        let pkg = Package::parse::<IO>(&lines(opf), &rootfile(), ParseMode::Strict).unwrap();
+        let series = pkg.metadata.series().unwrap();
+        assert_eq!(series.name, "Saga");
+        assert_eq!(series.kind, Some(CollectionType::Series));
+        assert_eq!(series.position, Some(4.0));
+        // the index goes with the series it follows, not the first one
+        let opf = "<package version=\"3.0\" unique-identifier=\"uid\">
+<metadata>
+<meta property=\"belongs-to-collection\" id=\"c1\">Saga</meta>
+<meta name=\"calibre:series\" content=\"Saga\"/>
+<meta name=\"calibre:series_index\" content=\"4\"/>
+<meta name=\"calibre:series\" content=\"Other Saga\"/>
+<meta name=\"calibre:series_index\" content=\"2.5\"/>
+</metadata>
+</package>
+";
+        let pkg = Package::parse::<IO>(&lines(opf), &rootfile(), ParseMode::Strict).unwrap();
+        let collections = pkg.metadata.collections();
+        assert_eq!(collections.len(), 2);
+        assert_eq!(collections[1].name, "Other Saga");
+        assert_eq!(collections[1].position, Some(2.5));
+        let pkg = Package::parse::<IO>(&lines(BAD_OPF), &rootfile(), ParseMode::Lenient).unwrap();
+        assert!(pkg.metadata.series().is_none());
+    }
+
+    #[test]
+    fn test_authors_without_roles() {
+        let pkg =
+            Package::parse::<IO>(&lines(METADATA_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        assert_eq!(pkg.metadata.authors().len(), 2);
+        assert_eq!(pkg.metadata.author_sort_key(), Some("First Author"));
+    }
+
+    const OPF2_METADATA: &str =
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"2.0\" unique-identifier=\"uid\">
+<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">
+<dc:identifier id=\"uid\" opf:scheme=\"ISBN\">9780000000001</dc:identifier>
+<dc:title>The Book</dc:title>
+<dc:creator opf:role=\"ill\">An Illustrator</dc:creator>
+<dc:creator opf:role=\"aut\" opf:file-as=\"Author, First\">First Author</dc:creator>
+<dc:contributor opf:role=\"edt\" opf:file-as=\"Editor, An\">An Editor</dc:contributor>
+</metadata>
+<spine toc=\"ncx\"></spine>
+</package>
+";
+
+    #[test]
+    fn test_opf2_attributes() {
+        let pkg =
+            Package::parse::<IO>(&lines(OPF2_METADATA), &rootfile(), ParseMode::Strict).unwrap();
+        let md = &pkg.metadata;
+        let authors = md.authors();
+        assert_eq!(authors.len(), 1);
+        assert_eq!(authors[0].value, "First Author");
+        assert_eq!(authors[0].file_as(), Some("Author, First"));
+        assert_eq!(md.author_sort_key(), Some("Author, First"));
+        assert_eq!(md.creators_with_role("ill").len(), 1);
+        let editor = &md.contributors()[0];
+        assert_eq!(editor.role(), Some("edt"));
+        assert_eq!(editor.sort_key(), "Editor, An");
+        assert_eq!(pkg.identifier().unwrap().identifier_type(), Some("ISBN"));
+    }
+
+    const EPUB3_OPF: &str =
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"uid\">
+<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+<dc:identifier id=\"uid\">urn:uuid:1234</dc:identifier>
+</metadata>
+<manifest>
+<item id=\"ch1\" href=\"ch1.xhtml\" media-type=\"application/xhtml+xml\"/>
+<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>
+</manifest>
+<spine>
+<itemref idref=\"ch1\"/>
+</spine>
+</package>
+";
+
+    #[test]
+    fn test_toc_source() {
+        let pkg = Package::parse::<IO>(&lines(EPUB3_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        assert_eq!(pkg.spine.toc, None);
+        assert!(pkg.ncx_item().is_none());
+        match pkg.toc_source() {
+            Some(TocSource::Nav(item)) => assert_eq!(item.id, "nav"),
+            _ => panic!(),
+        }
+        // the NCX is used when there is no nav document
+        let mut pkg =
+            Package::parse::<IO>(&lines(BAD_OPF), &rootfile(), ParseMode::Lenient).unwrap();
+        match pkg.toc_source() {
+            Some(TocSource::Ncx(item)) => assert_eq!(item.href, "toc.ncx"),
+            _ => panic!(),
+        }
+        // a spine toc id that isn't in the manifest falls back to the media type
+        pkg.spine.toc = Some(String::from("missing"));
+        assert_eq!(pkg.ncx_item().unwrap().href, "toc.ncx");
+    }
+
+    const GUIDE_OPF: &str = "<package version=\"2.0\" unique-identifier=\"uid\">
+<manifest>
+<item id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>
+<item id=\"title\" href=\"text/title.xhtml\" media-type=\"application/xhtml+xml\"/>
+<item id=\"copy\" href=\"text/copy.xhtml\" media-type=\"application/xhtml+xml\"/>
+<item id=\"ch1\" href=\"text/ch1.xhtml\" media-type=\"application/xhtml+xml\"/>
+</manifest>
+<spine toc=\"ncx\">
+<itemref idref=\"cover\"/>
+<itemref idref=\"title\"/>
+<itemref idref=\"copy\"/>
+<itemref idref=\"ch1\"/>
+</spine>
+<guide>
+<reference type=\"cover\" title=\"Cover\" href=\"cover.xhtml\"/>
+<reference type=\"title-page\" href=\"text/title.xhtml\"/>
+<reference type=\"copyright-page\" title=\"Copyright\" href=\"text/copy.xhtml\"/>
+<reference type=\"other.ms-coverimage\" href=\"http://example.com/c.jpg\"/>
+</guide>
+</package>
+";
+
+    #[test]
+    fn test_guide() {
+        let mut pkg =
+            Package::parse::<IO>(&lines(GUIDE_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        // the remote reference is skipped
+        assert_eq!(pkg.guide.len(), 3);
+        assert_eq!(pkg.guide[0].kind, LandmarkType::Cover);
+        assert_eq!(pkg.guide[0].title, "Cover");
+        assert_eq!(pkg.guide[1].kind, LandmarkType::TitlePage);
+        assert_eq!(pkg.guide[2].href.path, "OEBPS/text/copy.xhtml");
+        // skips the cover, title and copyright pages
+        let start = pkg.start_position(&pkg.guide).unwrap();
+        assert_eq!(start.index, 3);
+        // a bodymatter landmark wins
+        let text = Landmark {
+            kind: LandmarkType::from_type("text"),
+            title: String::new(),
+            href: io::resolve_href(&pkg.path, "text/title.xhtml#start").unwrap(),
+        };
+        pkg.guide.push(text);
+        let start = pkg.start_position(&pkg.guide).unwrap();
+        assert_eq!(start.index, 1);
+        assert_eq!(start.fragment.as_deref(), Some("start"));
+    }
+
+    #[test]
+    fn test_parse_missing_version() {
+        let doc = "<package unique-identifier=\"uid\"><spine></spine></package>";
+        match Package::parse::<IO>(&lines(doc), &rootfile(), ParseMode::Strict) {
+            Err(EPubError::Parse(e)) => {
+                assert_eq!(e.element, "package");
+                assert_eq!(e.kind, ParseErrorKind::MissingAttribute("version"));
+            }
+            _ => panic!(),
+        }
+        let pkg = Package::parse::<IO>(&lines(doc), &rootfile(), ParseMode::Lenient).unwrap();
+        assert_eq!(pkg.warnings.len(), 1);
+    }
+
+    const COVER_OPF: &str = "<package version=\"2.0\" unique-identifier=\"uid\">
+<metadata>
+<meta name=\"cover\" content=\"cover-jpg\"/>
+</metadata>
+<manifest>
+<item id=\"cover-jpg\" href=\"images/cover.jpg\" media-type=\"image/jpeg\"/>
+<item id=\"plate\" href=\"images/plate.png\" media-type=\"image/png\"/>
+<item id=\"cover\" href=\"text/cover.xhtml\" media-type=\"application/xhtml+xml\"/>
+</manifest>
+<spine>
+<itemref idref=\"cover\"/>
+</spine>
+</package>
+";
+
+    #[test]
+    fn test_cover_item() {
+        let pkg = Package::parse::<IO>(&lines(COVER_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        assert_eq!(pkg.cover_item().unwrap().id, "cover-jpg");
+        let opf = COVER_OPF
+            .replace("<meta name=\"cover\" content=\"cover-jpg\"/>", "")
+            .replace("image/png\"", "image/png\" properties=\"cover-image\"");
+        let pkg = Package::parse::<IO>(&lines(&opf), &rootfile(), ParseMode::Strict).unwrap();
+        assert_eq!(pkg.cover_item().unwrap().id, "plate");
+        let opf = COVER_OPF.replace("content=\"cover-jpg\"", "content=\"cover\"");
+        let pkg = Package::parse::<IO>(&lines(&opf), &rootfile(), ParseMode::Strict).unwrap();
+        assert!(pkg.cover_item().is_none());
+    }
+
+    #[test]
+    fn test_cover_fallback() {
+        // the cover item's href is outside the container
+        let opf = COVER_OPF.replace("images/cover.jpg", "../../cover.jpg");
+        let pkg = Package::parse::<IO>(&lines(&opf), &rootfile(), ParseMode::Strict).unwrap();
+        assert_eq!(pkg.cover_item().unwrap().id, "cover-jpg");
+        let mut disk =
+            fatfs::StdIoWrapper::from(std::io::Cursor::new(alloc::vec![0u8; 1024 * 1024]));
+        fatfs::format_volume(&mut disk, fatfs::FormatVolumeOptions::new()).unwrap();
+        let mut fs = FileSystem::new(disk, fatfs::FsOptions::new()).unwrap();
+        if io::create_dirs("book/OEBPS/text", &mut fs).is_err() {
+            panic!();
+        }
+        let page = "<html><body><img src=\"../images/plate.png\"/></body></html>";
+        let mut file = fs
+            .root_dir()
+            .create_file("book/OEBPS/text/cover.xhtml")
+            .unwrap();
+        fatfs::Write::write_all(&mut file, page.as_bytes()).unwrap();
+        drop(file);
+        // the first spine item is read for its image instead
+        let cover = pkg.cover(&[], &mut fs).unwrap();
+        assert_eq!(cover.path, "book/OEBPS/images/plate.png");
+    }
+
+    #[test]
+    fn test_image_on_page() {
+        let pkg = Package::parse::<IO>(&lines(COVER_OPF), &rootfile(), ParseMode::Strict).unwrap();
+        let page = pkg.resolve("text/cover.xhtml").unwrap();
+        let svg = "<html xmlns=\"http://www.w3.org/1999/xhtml\"><body>
+<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\">
+<image width=\"600\" height=\"800\" xlink:href=\"../images/plate.png\"/>
+</svg>
+<img src=\"../images/cover.jpg\"/>
+</body></html>
+";
+        let cover = pkg.image_on_page(&page, &lines(svg)).unwrap();
+        assert_eq!(cover.href.path, "OEBPS/images/plate.png");
+        assert_eq!(cover.path, "book/OEBPS/images/plate.png");
+        assert_eq!(cover.media_type, "image/png");
+        let img = "<html><body><p><img alt=\"\" src=\"../images/cover.jpg\"/></p></body></html>";
+        let cover = pkg.image_on_page(&page, &lines(img)).unwrap();
+        assert_eq!(cover.media_type, "image/jpeg");
+        let missing = "<html><body><img src=\"../images/missing.jpg\"/></body></html>";
+        assert!(pkg.image_on_page(&page, &lines(missing)).is_none());
+        assert!(pkg
+            .image_on_page(&page, &lines("<html><body/></html>"))
+            .is_none());
+    }
 }
@@ -0,0 +1,660 @@
+//! mounting FAT volumes from a raw block device
+//!
+//! A `BlockDevice` reads and writes whole blocks, such as the sectors of an
+//! SD card. A `PartitionSlice` cuts one partition out of the device and
+//! provides the byte oriented IO traits that fatfs needs, caching the
+//! current block. `mount_volume` ties these together with the partition
+//! tables in the `mbr` module.
+//!
+//! For provisioning, `provision` writes a partition table with a single FAT
+//! partition to a blank device, formats it and creates the library layout.
+
+use crate::mbr::{
+    self, Block, BlockCount, BlockIdx, GptEntry, Guid, Partition, PartitionKind, PartitionTable,
+    VolumeIdx,
+};
+use crate::{EPubError, EPubFile};
+use alloc::string::String;
+use core::fmt;
+use fatfs::{
+    DefaultTimeProvider, FileSystem, FormatVolumeOptions, FsOptions, IoBase, IoError,
+    LossyOemCpConverter, OemCpConverter, Read, Seek, SeekFrom, TimeProvider, Write,
+};
+use log::{info, trace};
+
+/// A device that reads and writes whole blocks
+pub trait BlockDevice {
+    /// the error type of the device
+    type Error: fmt::Debug;
+
+    /// read the block at `idx`
+    fn read_block(&mut self, idx: BlockIdx, block: &mut Block) -> Result<(), Self::Error>;
+
+    /// write the block at `idx`
+    fn write_block(&mut self, idx: BlockIdx, block: &Block) -> Result<(), Self::Error>;
+
+    /// the number of blocks on the device
+    fn num_blocks(&mut self) -> Result<BlockCount, Self::Error>;
+}
+
+impl<D: BlockDevice> BlockDevice for &mut D {
+    type Error = D::Error;
+
+    fn read_block(&mut self, idx: BlockIdx, block: &mut Block) -> Result<(), Self::Error> {
+        (**self).read_block(idx, block)
+    }
+
+    fn write_block(&mut self, idx: BlockIdx, block: &Block) -> Result<(), Self::Error> {
+        (**self).write_block(idx, block)
+    }
+
+    fn num_blocks(&mut self) -> Result<BlockCount, Self::Error> {
+        (**self).num_blocks()
+    }
+}
+
+/// errors from a `PartitionSlice`
+#[derive(Debug, PartialEq, Eq)]
+pub enum VolumeError<E> {
+    /// the block device failed
+    Device(E),
+    /// read past the end of the partition
+    UnexpectedEof,
+    /// write past the end of the partition
+    WriteZero,
+    /// seek outside the partition
+    InvalidSeek,
+}
+
+impl<E: fmt::Debug> IoError for VolumeError<E> {
+    fn is_interrupted(&self) -> bool {
+        false
+    }
+
+    fn new_unexpected_eof_error() -> Self {
+        VolumeError::UnexpectedEof
+    }
+
+    fn new_write_zero_error() -> Self {
+        VolumeError::WriteZero
+    }
+}
+
+/// A partition of a block device, read and written as a stream of bytes
+///
+/// One block is cached. Writes are kept in the cache until another block is
+/// needed or `flush` is called, fatfs flushes when the filesystem is unmounted.
+pub struct PartitionSlice<D: BlockDevice> {
+    device: D,
+    partition: Partition,
+    /// byte position in the partition
+    pos: u64,
+    /// the cached block
+    cache: Block,
+    /// index of the cached block, relative to the start of the partition
+    cached_idx: Option<BlockIdx>,
+    /// the cached block has been written to
+    dirty: bool,
+}
+
+impl<D: BlockDevice> fmt::Debug for PartitionSlice<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartitionSlice")
+            .field("partition", &self.partition)
+            .field("pos", &self.pos)
+            .field("cached_idx", &self.cached_idx)
+            .field("dirty", &self.dirty)
+            .finish()
+    }
+}
+
+impl<D: BlockDevice> PartitionSlice<D> {
+    /// create a slice covering `partition` on the device
+    pub fn new(device: D, partition: Partition) -> PartitionSlice<D> {
+        PartitionSlice {
+            device,
+            partition,
+            pos: 0,
+            cache: Block::new(),
+            cached_idx: None,
+            dirty: false,
+        }
+    }
+
+    /// the partition this slice covers
+    pub fn partition(&self) -> Partition {
+        self.partition
+    }
+
+    /// flush the cache and give back the device
+    pub fn into_inner(mut self) -> Result<D, VolumeError<D::Error>> {
+        self.flush()?;
+        Ok(self.device)
+    }
+
+    /// length of the partition in bytes
+    fn len(&self) -> u64 {
+        u64::from(self.partition.num_blocks.0) * u64::from(Block::LEN_U32)
+    }
+
+    /// write the cached block back to the device, if it has changed
+    fn write_back(&mut self) -> Result<(), VolumeError<D::Error>> {
+        if let (true, Some(idx)) = (self.dirty, self.cached_idx) {
+            trace!("write back block {:?}", idx);
+            self.device
+                .write_block(self.partition.lba_start + BlockCount(idx.0), &self.cache)
+                .map_err(VolumeError::Device)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// make the block holding the current position the cached one
+    ///
+    /// with `overwrite` the old contents are not needed, so are not read
+    fn load(&mut self, overwrite: bool) -> Result<(), VolumeError<D::Error>> {
+        let idx = BlockIdx((self.pos / Block::LEN as u64) as u32);
+        if self.cached_idx == Some(idx) {
+            return Ok(());
+        }
+        self.write_back()?;
+        self.cached_idx = None;
+        if !overwrite {
+            self.device
+                .read_block(
+                    self.partition.lba_start + BlockCount(idx.0),
+                    &mut self.cache,
+                )
+                .map_err(VolumeError::Device)?;
+        }
+        self.cached_idx = Some(idx);
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice> IoBase for PartitionSlice<D> {
+    type Error = VolumeError<D::Error>;
+}
+
+impl<D: BlockDevice> Read for PartitionSlice<D> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.pos >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        self.load(false)?;
+        let offset = (self.pos % Block::LEN as u64) as usize;
+        let left = (self.len() - self.pos) as usize;
+        let n = buf.len().min(Block::LEN - offset).min(left);
+        buf[..n].copy_from_slice(&self.cache.contents[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<D: BlockDevice> Write for PartitionSlice<D> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.pos >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        let offset = (self.pos % Block::LEN as u64) as usize;
+        let left = (self.len() - self.pos) as usize;
+        let n = buf.len().min(Block::LEN - offset).min(left);
+        // a whole block is replaced, no need to read it first
+        self.load(n == Block::LEN)?;
+        self.cache.contents[offset..offset + n].copy_from_slice(&buf[..n]);
+        self.dirty = true;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.write_back()
+    }
+}
+
+impl<D: BlockDevice> Seek for PartitionSlice<D> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => offset_pos(self.len(), n),
+            SeekFrom::Current(n) => offset_pos(self.pos, n),
+        };
+        match new_pos {
+            Some(n) if n <= self.len() => {
+                self.pos = n;
+                Ok(n)
+            }
+            _ => Err(VolumeError::InvalidSeek),
+        }
+    }
+}
+
+/// add a signed offset to a position
+fn offset_pos(pos: u64, offset: i64) -> Option<u64> {
+    if offset < 0 {
+        pos.checked_sub(offset.unsigned_abs())
+    } else {
+        pos.checked_add(offset as u64)
+    }
+}
+
+/// the error type when mounting a volume from device `D`
+pub type MountError<D> = EPubError<PartitionSlice<D>>;
+
+/// Find the partition `volume_idx` on the device, from either an MBR or
+/// a GPT partition table. The partition must be one fatfs can mount.
+pub fn find_fat_partition<D: BlockDevice>(
+    device: &mut D,
+    volume_idx: VolumeIdx,
+) -> Result<Partition, MountError<D>> {
+    let device_err = |e| EPubError::IO(fatfs::Error::Io(VolumeError::Device(e)));
+    let mut block = Block::new();
+    device
+        .read_block(BlockIdx(0), &mut block)
+        .map_err(device_err)?;
+    if mbr::is_protective_mbr(&block) {
+        let last = match device.num_blocks().map_err(device_err)? {
+            BlockCount(n) if n > 2 => Some(BlockIdx(n - 1)),
+            _ => None,
+        };
+        let mut read_block =
+            |idx, block: &mut Block| device.read_block(idx, block).map_err(device_err);
+        let entry = mbr::gpt_entry(&mut read_block, last, volume_idx)?;
+        if entry.type_guid != Guid::BASIC_DATA && entry.type_guid != Guid::EFI_SYSTEM {
+            return Err(EPubError::FormatError("Not a FAT partition"));
+        }
+        entry.partition()
+    } else {
+        let mut read_block =
+            |idx, block: &mut Block| device.read_block(idx, block).map_err(device_err);
+        let partition = mbr::mbr_partition(&mut read_block, volume_idx)?;
+        match partition.kind() {
+            PartitionKind::Empty | PartitionKind::Extended => Err(EPubError::NoSuchVolume),
+            kind if kind.is_fat() => Ok(partition),
+            _ => Err(EPubError::FormatError("Not a FAT partition")),
+        }
+    }
+}
+
+/// Mount the FAT volume `volume_idx` of the device, with the default fatfs options
+pub fn mount_volume<D: BlockDevice>(
+    device: D,
+    volume_idx: VolumeIdx,
+) -> Result<FileSystem<PartitionSlice<D>, DefaultTimeProvider, LossyOemCpConverter>, MountError<D>>
+{
+    mount_volume_with_options(device, volume_idx, FsOptions::new())
+}
+
+/// Mount the FAT volume `volume_idx` of the device
+pub fn mount_volume_with_options<D, TP, OCC>(
+    mut device: D,
+    volume_idx: VolumeIdx,
+    options: FsOptions<TP, OCC>,
+) -> Result<FileSystem<PartitionSlice<D>, TP, OCC>, MountError<D>>
+where
+    D: BlockDevice,
+    TP: TimeProvider,
+    OCC: OemCpConverter,
+{
+    let partition = find_fat_partition(&mut device, volume_idx)?;
+    info!("Mounting volume {:?}: {:?}", volume_idx, partition);
+    let slice = PartitionSlice::new(device, partition);
+    Ok(FileSystem::new(slice, options)?)
+}
+
+/// How to lay out a blank device when provisioning it
+#[derive(Debug, Clone)]
+pub struct ProvisionOptions {
+    /// the kind of partition table to write
+    pub table: PartitionTable,
+    /// the partition starts on a multiple of this many blocks, 2048 is 1MB
+    pub align: BlockCount,
+    /// the MBR disk signature, and the FAT volume serial number
+    pub volume_id: u32,
+    /// the FAT volume label, padded with spaces
+    pub volume_label: [u8; 11],
+    /// the GPT disk guid, should be unique for each device. It has to be
+    /// set for a GPT, `Guid::EMPTY` is rejected
+    pub disk_guid: Guid,
+    /// the GPT partition guid, should be unique for each device. It has to
+    /// be set for a GPT, `Guid::EMPTY` is rejected
+    pub partition_guid: Guid,
+    /// the GPT partition name
+    pub partition_name: &'static str,
+}
+
+impl ProvisionOptions {
+    /// options for an MBR partitioned device, aligned to 1MB
+    pub fn new() -> ProvisionOptions {
+        ProvisionOptions {
+            table: PartitionTable::Mbr,
+            align: BlockCount(2048),
+            volume_id: 0,
+            volume_label: *b"EPUB       ",
+            disk_guid: Guid::EMPTY,
+            partition_guid: Guid::EMPTY,
+            partition_name: "EPUB",
+        }
+    }
+}
+
+impl Default for ProvisionOptions {
+    fn default() -> Self {
+        ProvisionOptions::new()
+    }
+}
+
+/// the MBR partition type for a FAT volume of `num_blocks`
+///
+/// matches the FAT type the fatfs formatter picks for the size
+fn fat_partition_id(num_blocks: BlockCount) -> u8 {
+    const MB_BLOCKS: u32 = 2048;
+    if num_blocks.0 < 4 * MB_BLOCKS {
+        mbr::PARTITION_ID_FAT12
+    } else if num_blocks.0 < 512 * MB_BLOCKS {
+        mbr::PARTITION_ID_FAT16_LBA
+    } else {
+        mbr::PARTITION_ID_FAT32_LBA
+    }
+}
+
+/// work out where the single FAT partition goes on a device of `total` blocks
+pub fn plan_partition<D: BlockDevice>(
+    total: BlockCount,
+    options: &ProvisionOptions,
+) -> Result<Partition, MountError<D>> {
+    let align = core::cmp::max(options.align.0, 1);
+    // the end of the space a partition can use, exclusive
+    let (first, end) = match options.table {
+        PartitionTable::Mbr => (1, total.0),
+        PartitionTable::Gpt => {
+            let reserved = 1 + mbr::GptHeader::ENTRIES_BLOCKS;
+            (1 + reserved, total.0.saturating_sub(reserved))
+        }
+    };
+    let start = match first.checked_add(align - 1) {
+        Some(n) => n / align * align,
+        None => return Err(EPubError::FormatError("Disk too small")),
+    };
+    // leave room for at least a tiny FAT12 volume
+    if end <= start || end - start < 64 {
+        return Err(EPubError::FormatError("Disk too small"));
+    }
+    let num_blocks = BlockCount(end - start);
+    let part_type = match options.table {
+        PartitionTable::Mbr => fat_partition_id(num_blocks),
+        // the basic data type guid, as `GptEntry::part_type` reports it
+        PartitionTable::Gpt => mbr::PARTITION_ID_FAT32_LBA,
+    };
+    Ok(Partition {
+        part_type,
+        lba_start: BlockIdx(start),
+        num_blocks,
+    })
+}
+
+/// Write a partition table holding a single FAT partition to the device
+///
+/// Everything on the device is lost. Returns the partition created.
+pub fn write_partition_table<D: BlockDevice>(
+    device: &mut D,
+    options: &ProvisionOptions,
+) -> Result<Partition, MountError<D>> {
+    if options.table == PartitionTable::Gpt
+        && (options.disk_guid == Guid::EMPTY || options.partition_guid == Guid::EMPTY)
+    {
+        return Err(EPubError::FormatError("GPT guids must be set"));
+    }
+    let device_err = |e| EPubError::IO(fatfs::Error::Io(VolumeError::Device(e)));
+    let total = device.num_blocks().map_err(device_err)?;
+    let partition = plan_partition::<D>(total, options)?;
+    info!(
+        "Writing {:?} partition table: {:?}",
+        options.table, partition
+    );
+    let mut write_block = |idx, block: &Block| device.write_block(idx, block).map_err(device_err);
+    match options.table {
+        PartitionTable::Mbr => {
+            write_block(
+                BlockIdx(0),
+                &mbr::mbr_block(&[partition], options.volume_id),
+            )?;
+        }
+        PartitionTable::Gpt => {
+            let entry = GptEntry {
+                type_guid: Guid::BASIC_DATA,
+                unique_guid: options.partition_guid,
+                first_lba: u64::from(partition.lba_start.0),
+                last_lba: u64::from(partition.lba_start.0) + u64::from(partition.num_blocks.0) - 1,
+                attributes: 0,
+                name: String::from(options.partition_name),
+            };
+            mbr::write_gpt(&mut write_block, total, options.disk_guid, &[entry])?;
+        }
+    }
+    Ok(partition)
+}
+
+/// Format the partition with a FAT volume, mount it and create the library layout
+pub fn format_partition<D: BlockDevice>(
+    device: D,
+    partition: Partition,
+    options: &ProvisionOptions,
+) -> Result<FileSystem<PartitionSlice<D>, DefaultTimeProvider, LossyOemCpConverter>, MountError<D>>
+{
+    let mut slice = PartitionSlice::new(device, partition);
+    info!("Formatting {:?}", partition);
+    fatfs::format_volume(
+        &mut slice,
+        FormatVolumeOptions::new()
+            .total_sectors(partition.num_blocks.0)
+            .volume_id(options.volume_id)
+            .volume_label(options.volume_label),
+    )?;
+    let io_err = |e| EPubError::IO(fatfs::Error::Io(e));
+    slice.flush().map_err(io_err)?;
+    slice.seek(SeekFrom::Start(0)).map_err(io_err)?;
+    let mut fs = FileSystem::new(slice, FsOptions::new())?;
+    EPubFile::create_layout(&mut fs)?;
+    Ok(fs)
+}
+
+/// Provision a blank device: partition it, format the FAT volume and create
+/// the library layout. The mounted filesystem is returned.
+pub fn provision<D: BlockDevice>(
+    mut device: D,
+    options: &ProvisionOptions,
+) -> Result<FileSystem<PartitionSlice<D>, DefaultTimeProvider, LossyOemCpConverter>, MountError<D>>
+{
+    let partition = write_partition_table(&mut device, options)?;
+    format_partition(device, partition, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// a block device in memory
+    struct RamDisk {
+        blocks: Vec<Block>,
+        reads: usize,
+    }
+
+    impl RamDisk {
+        fn new(n: usize) -> RamDisk {
+            RamDisk {
+                blocks: alloc::vec![Block::new(); n],
+                reads: 0,
+            }
+        }
+    }
+
+    impl BlockDevice for RamDisk {
+        type Error = ();
+
+        fn read_block(&mut self, idx: BlockIdx, block: &mut Block) -> Result<(), ()> {
+            self.reads += 1;
+            *block = self.blocks.get(idx.0 as usize).ok_or(())?.clone();
+            Ok(())
+        }
+
+        fn write_block(&mut self, idx: BlockIdx, block: &Block) -> Result<(), ()> {
+            *self.blocks.get_mut(idx.0 as usize).ok_or(())? = block.clone();
+            Ok(())
+        }
+
+        fn num_blocks(&mut self) -> Result<BlockCount, ()> {
+            Ok(BlockCount(self.blocks.len() as u32))
+        }
+    }
+
+    fn partition(start: u32, len: u32) -> Partition {
+        Partition {
+            part_type: mbr::PARTITION_ID_FAT32_LBA,
+            lba_start: BlockIdx(start),
+            num_blocks: BlockCount(len),
+        }
+    }
+
+    #[test]
+    fn test_slice_write_read() {
+        let mut slice = PartitionSlice::new(RamDisk::new(8), partition(2, 4));
+        let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        assert_eq!(slice.seek(SeekFrom::Start(300)), Ok(300));
+        let mut n = 0;
+        while n < data.len() {
+            n += slice.write(&data[n..]).unwrap();
+        }
+        slice.flush().unwrap();
+        let disk = slice.into_inner().unwrap();
+        // the partition starts at block 2
+        assert_eq!(disk.blocks[2].contents[300], 0);
+        assert_eq!(disk.blocks[2].contents[511], 211);
+        assert_eq!(disk.blocks[3].contents[0], 212);
+        assert_eq!(disk.blocks[4].contents[275], (999 % 256) as u8);
+        assert_eq!(disk.blocks[1].contents, [0; 512]);
+
+        let mut slice = PartitionSlice::new(disk, partition(2, 4));
+        slice.seek(SeekFrom::Start(300)).unwrap();
+        let mut buf = [0u8; 1000];
+        let mut n = 0;
+        while n < buf.len() {
+            n += slice.read(&mut buf[n..]).unwrap();
+        }
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn test_slice_bounds() {
+        let mut slice = PartitionSlice::new(RamDisk::new(8), partition(2, 4));
+        assert_eq!(slice.seek(SeekFrom::End(0)), Ok(2048));
+        let mut buf = [0u8; 16];
+        assert_eq!(slice.read(&mut buf), Ok(0));
+        assert_eq!(slice.write(&buf), Ok(0));
+        assert_eq!(slice.seek(SeekFrom::Current(-16)), Ok(2032));
+        assert_eq!(slice.read(&mut buf), Ok(16));
+        assert!(slice.seek(SeekFrom::End(1)).is_err());
+        assert!(slice.seek(SeekFrom::Current(-4096)).is_err());
+    }
+
+    #[test]
+    fn test_slice_cache() {
+        let mut slice = PartitionSlice::new(RamDisk::new(8), partition(0, 8));
+        let mut buf = [0u8; 8];
+        for _ in 0..32 {
+            slice.read(&mut buf).unwrap();
+        }
+        // 256 bytes all from the first block
+        assert_eq!(slice.device.reads, 1);
+        // a full block write does not read
+        slice.seek(SeekFrom::Start(1024)).unwrap();
+        slice.write(&[1u8; 512]).unwrap();
+        assert_eq!(slice.device.reads, 1);
+    }
+
+    #[test]
+    fn test_partition_table_mbr() {
+        let mut disk = RamDisk::new(8192);
+        let p = write_partition_table(&mut disk, &ProvisionOptions::new()).unwrap();
+        assert_eq!(p.lba_start, BlockIdx(2048));
+        assert_eq!(p.num_blocks, BlockCount(6144));
+        assert_eq!(find_fat_partition(&mut disk, VolumeIdx(0)).unwrap(), p);
+    }
+
+    #[test]
+    fn test_partition_table_gpt() {
+        let mut disk = RamDisk::new(8192);
+        let options = ProvisionOptions {
+            table: PartitionTable::Gpt,
+            disk_guid: Guid([1; 16]),
+            partition_guid: Guid([2; 16]),
+            ..ProvisionOptions::new()
+        };
+        let p = write_partition_table(&mut disk, &options).unwrap();
+        assert_eq!(p.lba_start, BlockIdx(2048));
+        // the backup table takes the last 33 blocks
+        assert_eq!(p.num_blocks, BlockCount(8192 - 33 - 2048));
+        assert_eq!(find_fat_partition(&mut disk, VolumeIdx(0)).unwrap(), p);
+    }
+
+    #[test]
+    fn test_partition_table_gpt_needs_guids() {
+        let mut disk = RamDisk::new(8192);
+        let options = ProvisionOptions {
+            table: PartitionTable::Gpt,
+            partition_guid: Guid([2; 16]),
+            ..ProvisionOptions::new()
+        };
+        assert!(write_partition_table(&mut disk, &options).is_err());
+        let options = ProvisionOptions {
+            table: PartitionTable::Gpt,
+            disk_guid: Guid([1; 16]),
+            ..ProvisionOptions::new()
+        };
+        assert!(provision(&mut disk, &options).is_err());
+        // nothing was written
+        assert!(disk.blocks[0].contents.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_disk_too_small() {
+        let mut disk = RamDisk::new(2048);
+        assert!(write_partition_table(&mut disk, &ProvisionOptions::new()).is_err());
+    }
+
+    #[test]
+    fn test_provision() {
+        let mut disk = RamDisk::new(16384);
+        {
+            let fs = provision(&mut disk, &ProvisionOptions::new()).unwrap();
+            let root_dir = fs.root_dir();
+            assert!(root_dir.open_dir(EPubFile::CUR_BOOK_DIR).is_ok());
+            assert!(root_dir.open_dir(EPubFile::BOOKS_DIR).is_ok());
+        }
+        let p = find_fat_partition(&mut disk, VolumeIdx(0)).unwrap();
+        assert_eq!(p.kind(), PartitionKind::Fat16);
+    }
+
+    #[test]
+    fn test_find_fat_partition() {
+        let mut disk = RamDisk::new(16);
+        let mbr = &mut disk.blocks[0].contents;
+        mbr[510] = 0x55;
+        mbr[511] = 0xAA;
+        mbr[446 + 4] = mbr::PARTITION_ID_FAT16;
+        mbr[446 + 8] = 4;
+        mbr[446 + 12] = 12;
+        mbr[462 + 4] = 0x83;
+        mbr[462 + 8] = 1;
+        mbr[462 + 12] = 1;
+        let p = find_fat_partition(&mut disk, VolumeIdx(0)).unwrap();
+        assert_eq!(p.lba_start, BlockIdx(4));
+        assert_eq!(p.num_blocks, BlockCount(12));
+        assert!(find_fat_partition(&mut disk, VolumeIdx(1)).is_err());
+        assert!(matches!(
+            find_fat_partition(&mut disk, VolumeIdx(2)),
+            Err(EPubError::NoSuchVolume)
+        ));
+    }
+}